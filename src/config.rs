@@ -0,0 +1,938 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::SyncError;
+use crate::plan::{ProjectDefaults, Settings};
+use crate::rules::{
+    ArchiveRules, BudgetRules, BudgetSource, OrphanAction, OrphanRules, ProjectRule, ProjectRules,
+    Resolution, StatusAction, StatusRules,
+};
+use crate::schedule::{self, Schedule};
+use crate::time_entries::TimeEntrySettings;
+use crate::webhook::WebhookConfig;
+use crate::{harvest, rentman};
+
+const BILL_BY: [&str; 4] = ["Project", "Tasks", "People", "none"];
+const BUDGET_BY: [&str; 6] = [
+    "project",
+    "project_cost",
+    "task",
+    "task_fees",
+    "person",
+    "none",
+];
+
+pub struct Config {
+    pub harvest: HarvestConfig,
+    pub rentman: RentmanConfig,
+    pub mapping_file: PathBuf,
+    pub settings: Settings,
+    /// When `serve` runs a sync, if configured.
+    pub schedule: Option<Schedule>,
+    /// Where `serve` receives Rentman webhooks, if configured.
+    pub webhook: Option<WebhookConfig>,
+    /// How often an incremental sync is replaced by a full one, or `None` to always sync everything.
+    pub full_sync_every: Option<Duration>,
+}
+
+pub struct HarvestConfig {
+    pub token: String,
+    pub account_id: String,
+    pub user_agent: String,
+    pub base_url: String,
+}
+
+pub struct RentmanConfig {
+    pub token: String,
+    pub base_url: String,
+}
+
+/// The config file as written. Every key is optional here, so environment variables can fill
+/// them in and all missing keys can be reported at once.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    harvest: RawHarvest,
+    rentman: RawRentman,
+    mapping_file: Option<PathBuf>,
+    filters: RawFilters,
+    projects: RawProjects,
+    project_defaults: RawProjectDefaults,
+    statuses: RawStatuses,
+    archive: RawArchive,
+    budget: RawBudget,
+    orphans: RawOrphans,
+    schedule: RawSchedule,
+    webhook: RawWebhook,
+    incremental: RawIncremental,
+    time_entries: RawTimeEntries,
+    tasks: RawTasks,
+    users: RawUsers,
+    /// Rentman crew member ID by Harvest user ID. TOML keys are strings.
+    crew: HashMap<String, i64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawHarvest {
+    token: Option<String>,
+    account_id: Option<String>,
+    user_agent: Option<String>,
+    nvt_client: Option<i64>,
+    base_url: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawRentman {
+    token: Option<String>,
+    base_url: Option<String>,
+}
+
+/// Shorthands for the exclude rules that used to be built in.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawFilters {
+    excluded_customers: Option<Vec<i64>>,
+    skip_template_names: Option<bool>,
+    skip_template_subprojects: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawProjects {
+    exclude: Vec<RawProjectRule>,
+    include: Vec<RawProjectRule>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawProjectRule {
+    name: Option<String>,
+    customers: Vec<i64>,
+    name_regex: Option<String>,
+    tags: Vec<String>,
+    statuses: Vec<String>,
+    template_subproject: Option<bool>,
+    /// e.g. "2024-01-01"
+    ends_before: Option<NaiveDate>,
+    starts_after: Option<NaiveDate>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawProjectDefaults {
+    bill_by: Option<String>,
+    budget_by: Option<String>,
+    is_billable: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawStatuses {
+    resolution: Option<Resolution>,
+    default: Option<StatusAction>,
+    /// Action by Rentman status name.
+    mapping: HashMap<String, StatusAction>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawArchive {
+    after_days: Option<i64>,
+    reactivate_within_days: Option<i64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawBudget {
+    source: Option<BudgetSource>,
+    notify_when_over_budget: Option<bool>,
+    over_budget_notification_percentage: Option<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawOrphans {
+    archive_clients: Option<bool>,
+    keep_clients: Option<Vec<i64>>,
+    projects: Option<OrphanAction>,
+    tag: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawSchedule {
+    /// e.g. "15m"
+    interval: Option<String>,
+    cron: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawWebhook {
+    /// e.g. "0.0.0.0:8080"
+    listen: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawIncremental {
+    enabled: Option<bool>,
+    /// e.g. "24h"
+    full_sync_every: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawTimeEntries {
+    enabled: Option<bool>,
+    days: Option<i64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawTasks {
+    enabled: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RawUsers {
+    enabled: Option<bool>,
+}
+
+impl Config {
+    /// Reads the config file, if any, and applies environment variables on top of it.
+    pub fn load(path: Option<&Path>) -> Result<Config, SyncError> {
+        let raw = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(|e| {
+                    SyncError::Config(format!("Can't read {}: {}", path.display(), e))
+                })?;
+
+                toml::from_str::<RawConfig>(&text).map_err(|e| {
+                    SyncError::Config(format!("Invalid config file {}: {}", path.display(), e))
+                })?
+            }
+            None => RawConfig::default(),
+        };
+
+        Self::from_raw(raw, &Env::from_process())
+    }
+
+    /// Applies environment variables on top of the config file and checks the result.
+    fn from_raw(mut raw: RawConfig, env: &Env) -> Result<Config, SyncError> {
+        let mut errors: Vec<String> = vec![];
+
+        raw.apply_env(env, &mut errors);
+
+        let config = raw.validate(&mut errors);
+
+        match config {
+            Some(config) if errors.is_empty() => Ok(config),
+            _ => Err(SyncError::Config(format!(
+                "Invalid configuration:\n  {}",
+                errors.join("\n  ")
+            ))),
+        }
+    }
+}
+
+impl RawConfig {
+    fn apply_env(&mut self, env: &Env, errors: &mut Vec<String>) {
+        env.string(&mut self.harvest.token, "HARVEST_TOKEN");
+        env.string(&mut self.harvest.account_id, "HARVEST_ACCOUNT_ID");
+        env.string(&mut self.harvest.user_agent, "HARVEST_USER_AGENT");
+        env.parse(&mut self.harvest.nvt_client, "HARVEST_NVT_CLIENT", errors);
+        env.string(&mut self.harvest.base_url, "HARVEST_BASE_URL");
+
+        env.string(&mut self.rentman.token, "RENTMAN_TOKEN");
+        env.string(&mut self.rentman.base_url, "RENTMAN_BASE_URL");
+
+        if let Some(path) = env.var("MAPPING_FILE") {
+            self.mapping_file = Some(PathBuf::from(path));
+        }
+
+        env.ids(
+            &mut self.filters.excluded_customers,
+            "RENTMAN_EXCLUDED_CUSTOMERS",
+            errors,
+        );
+
+        // Single excluded customer, from before the config file existed
+        let mut btdb_id: Option<i64> = None;
+        env.parse(&mut btdb_id, "RENTMAN_BTDB_ID", errors);
+
+        if let Some(id) = btdb_id {
+            self.filters
+                .excluded_customers
+                .get_or_insert_with(Vec::new)
+                .push(id);
+        }
+
+        env.string(&mut self.project_defaults.bill_by, "HARVEST_BILL_BY");
+        env.string(&mut self.project_defaults.budget_by, "HARVEST_BUDGET_BY");
+        env.parse(
+            &mut self.project_defaults.is_billable,
+            "HARVEST_IS_BILLABLE",
+            errors,
+        );
+
+        env.parse(
+            &mut self.statuses.resolution,
+            "RENTMAN_STATUS_RESOLUTION",
+            errors,
+        );
+
+        if let Some(value) = env.var("RENTMAN_STATUS_MAPPING") {
+            let mut rules = StatusRules::default();
+
+            match rules.parse_actions(&value) {
+                Ok(()) => self.statuses.mapping.extend(rules.actions),
+                Err(e) => errors.push(format!("RENTMAN_STATUS_MAPPING: {}", e)),
+            }
+        }
+
+        env.parse(&mut self.budget.source, "BUDGET_SOURCE", errors);
+        env.parse(
+            &mut self.budget.notify_when_over_budget,
+            "BUDGET_NOTIFY",
+            errors,
+        );
+        env.parse(
+            &mut self.budget.over_budget_notification_percentage,
+            "BUDGET_NOTIFY_PERCENTAGE",
+            errors,
+        );
+
+        env.parse(
+            &mut self.orphans.archive_clients,
+            "ORPHANS_ARCHIVE_CLIENTS",
+            errors,
+        );
+        env.ids(
+            &mut self.orphans.keep_clients,
+            "ORPHANS_KEEP_CLIENTS",
+            errors,
+        );
+        env.parse(&mut self.orphans.projects, "ORPHANS_PROJECTS", errors);
+        env.string(&mut self.orphans.tag, "ORPHANS_TAG");
+
+        env.parse(&mut self.archive.after_days, "ARCHIVE_AFTER_DAYS", errors);
+        env.parse(
+            &mut self.archive.reactivate_within_days,
+            "REACTIVATE_WITHIN_DAYS",
+            errors,
+        );
+
+        env.parse(&mut self.incremental.enabled, "SYNC_INCREMENTAL", errors);
+        env.string(&mut self.incremental.full_sync_every, "SYNC_FULL_EVERY");
+
+        env.parse(&mut self.tasks.enabled, "SYNC_TASKS", errors);
+        env.parse(&mut self.users.enabled, "SYNC_USERS", errors);
+
+        env.parse(
+            &mut self.time_entries.enabled,
+            "TIME_ENTRIES_ENABLED",
+            errors,
+        );
+        env.parse(&mut self.time_entries.days, "TIME_ENTRIES_DAYS", errors);
+
+        if let Some(value) = env.var("CREW_MAPPING") {
+            for pair in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+                match pair
+                    .split_once('=')
+                    .and_then(|(user, crew)| Some((user.trim(), crew.trim().parse().ok()?)))
+                {
+                    Some((user, crew)) => {
+                        self.crew.insert(user.to_string(), crew);
+                    }
+                    None => errors.push(format!(
+                        "CREW_MAPPING: \"{}\" is not a user=crew pair of IDs",
+                        pair
+                    )),
+                }
+            }
+        }
+
+        env.string(&mut self.webhook.listen, "WEBHOOK_LISTEN");
+        env.string(&mut self.webhook.token, "WEBHOOK_TOKEN");
+
+        // Either one replaces a schedule from the config file
+        if let Some(interval) = env.var("SYNC_INTERVAL") {
+            self.schedule = RawSchedule {
+                interval: Some(interval),
+                cron: None,
+            };
+        }
+
+        if let Some(cron) = env.var("SYNC_CRON") {
+            self.schedule = RawSchedule {
+                interval: None,
+                cron: Some(cron),
+            };
+        }
+    }
+
+    /// Checks required keys and values, filling in defaults for optional keys.
+    fn validate(self, errors: &mut Vec<String>) -> Option<Config> {
+        let harvest_token = required(
+            non_blank(self.harvest.token),
+            "harvest.token",
+            "HARVEST_TOKEN",
+            errors,
+        );
+        let account_id = required(
+            non_blank(self.harvest.account_id),
+            "harvest.account_id",
+            "HARVEST_ACCOUNT_ID",
+            errors,
+        );
+        let user_agent = required(
+            non_blank(self.harvest.user_agent),
+            "harvest.user_agent",
+            "HARVEST_USER_AGENT",
+            errors,
+        );
+        let nvt_client = required(
+            self.harvest.nvt_client,
+            "harvest.nvt_client",
+            "HARVEST_NVT_CLIENT",
+            errors,
+        );
+        let rentman_token = required(
+            non_blank(self.rentman.token),
+            "rentman.token",
+            "RENTMAN_TOKEN",
+            errors,
+        );
+
+        if nvt_client.is_some_and(|x| x <= 0) {
+            errors.push(
+                "harvest.nvt_client must be the ID of a Harvest client, set it in the config file or HARVEST_NVT_CLIENT"
+                    .to_string(),
+            );
+        }
+
+        let project_defaults = ProjectDefaults {
+            bill_by: self.project_defaults.bill_by.unwrap_or("none".to_string()),
+            budget_by: self
+                .project_defaults
+                .budget_by
+                .unwrap_or("none".to_string()),
+            is_billable: self.project_defaults.is_billable.unwrap_or(true),
+        };
+
+        if !BILL_BY.contains(&project_defaults.bill_by.as_str()) {
+            errors.push(format!(
+                "project_defaults.bill_by must be one of {}",
+                BILL_BY.join(", ")
+            ));
+        }
+
+        if !BUDGET_BY.contains(&project_defaults.budget_by.as_str()) {
+            errors.push(format!(
+                "project_defaults.budget_by must be one of {}",
+                BUDGET_BY.join(", ")
+            ));
+        }
+
+        let mut statuses = StatusRules::default();
+
+        statuses.actions.extend(
+            self.statuses
+                .mapping
+                .into_iter()
+                .map(|(k, v)| (k.to_lowercase(), v)),
+        );
+
+        if let Some(default) = self.statuses.default {
+            statuses.default = default;
+        }
+
+        if let Some(resolution) = self.statuses.resolution {
+            statuses.resolution = resolution;
+        }
+
+        let mut archive = ArchiveRules {
+            statuses,
+            ..Default::default()
+        };
+
+        if let Some(days) = self.archive.after_days {
+            archive.archive_after_days = days;
+        }
+
+        if let Some(days) = self.archive.reactivate_within_days {
+            archive.reactivate_within_days = days;
+        }
+
+        let notify_at = self
+            .budget
+            .notify_when_over_budget
+            .unwrap_or(false)
+            .then(|| {
+                self.budget
+                    .over_budget_notification_percentage
+                    .unwrap_or(100.0)
+            });
+
+        if notify_at.is_some_and(|x| x <= 0.0) {
+            errors.push("budget.over_budget_notification_percentage must be above 0".to_string());
+        }
+
+        let budget = self
+            .budget
+            .source
+            .map(|source| BudgetRules { source, notify_at });
+
+        let orphans = OrphanRules {
+            archive_clients: self.orphans.archive_clients.unwrap_or(false),
+            keep_clients: self.orphans.keep_clients.unwrap_or_default(),
+            projects: self.orphans.projects.unwrap_or(OrphanAction::Report),
+            tag: self
+                .orphans
+                .tag
+                .map(|x| x.trim().to_string())
+                .unwrap_or("[Deleted in Rentman]".to_string()),
+        };
+
+        if orphans.projects == OrphanAction::Tag && orphans.tag.is_empty() {
+            errors.push("orphans.tag can't be empty when orphans.projects is tag".to_string());
+        }
+
+        let mut projects = ProjectRules {
+            exclude: project_rules(self.projects.exclude, "projects.exclude", errors),
+            include: project_rules(self.projects.include, "projects.include", errors),
+        };
+
+        // The old filters are exclude rules too, checked first
+        let mut filters = vec![];
+
+        if self.filters.skip_template_names.unwrap_or(true) {
+            filters.push(ProjectRule {
+                name: "filters.skip_template_names".to_string(),
+                name_regex: Regex::new("(?i)template").ok(),
+                ..Default::default()
+            });
+        }
+
+        if let Some(customers) = self.filters.excluded_customers.filter(|x| !x.is_empty()) {
+            filters.push(ProjectRule {
+                name: "filters.excluded_customers".to_string(),
+                customers,
+                ..Default::default()
+            });
+        }
+
+        if self.filters.skip_template_subprojects.unwrap_or(true) {
+            filters.push(ProjectRule {
+                name: "filters.skip_template_subprojects".to_string(),
+                template_subproject: Some(true),
+                ..Default::default()
+            });
+        }
+
+        projects.exclude.splice(0..0, filters);
+
+        let schedule = match (&self.schedule.interval, &self.schedule.cron) {
+            (Some(_), Some(_)) => {
+                errors.push("schedule.interval and schedule.cron can't both be set".to_string());
+                None
+            }
+            (Some(interval), None) => Schedule::parse_interval(interval)
+                .map_err(|e| errors.push(format!("schedule.interval: {}", e)))
+                .ok(),
+            (None, Some(cron)) => Schedule::parse_cron(cron)
+                .map_err(|e| errors.push(format!("schedule.cron: {}", e)))
+                .ok(),
+            (None, None) => None,
+        };
+
+        let full_sync_every = match self.incremental.full_sync_every.as_deref() {
+            Some(value) => schedule::parse_duration(value)
+                .map_err(|e| errors.push(format!("incremental.full_sync_every: {}", e)))
+                .ok(),
+            None => Some(Duration::from_secs(24 * 60 * 60)),
+        }
+        .filter(|_| self.incremental.enabled.unwrap_or(true));
+
+        let mut crew = HashMap::new();
+
+        for (user, crew_id) in self.crew {
+            match user.trim().parse::<i64>() {
+                Ok(user) => {
+                    crew.insert(user, crew_id);
+                }
+                Err(_) => errors.push(format!("crew: \"{}\" is not a Harvest user ID", user)),
+            }
+        }
+
+        let time_entries =
+            self.time_entries
+                .enabled
+                .unwrap_or(false)
+                .then_some(TimeEntrySettings {
+                    days: self.time_entries.days.unwrap_or(30),
+                });
+
+        let webhook = match self.webhook.listen {
+            Some(listen) => {
+                let listen = listen
+                    .parse()
+                    .map_err(|_| {
+                        errors.push(format!(
+                            "webhook.listen: \"{}\" is not an address like 0.0.0.0:8080",
+                            listen
+                        ))
+                    })
+                    .ok();
+                let token = required(
+                    non_blank(self.webhook.token),
+                    "webhook.token",
+                    "WEBHOOK_TOKEN",
+                    errors,
+                );
+
+                Some(WebhookConfig {
+                    listen: listen?,
+                    token: token?,
+                })
+            }
+            None => None,
+        };
+
+        Some(Config {
+            harvest: HarvestConfig {
+                token: harvest_token?,
+                account_id: account_id?,
+                user_agent: user_agent?,
+                base_url: self
+                    .harvest
+                    .base_url
+                    .unwrap_or(harvest::DEFAULT_BASE_URL.to_string()),
+            },
+            rentman: RentmanConfig {
+                token: rentman_token?,
+                base_url: self
+                    .rentman
+                    .base_url
+                    .unwrap_or(rentman::DEFAULT_BASE_URL.to_string()),
+            },
+            mapping_file: self.mapping_file.unwrap_or(PathBuf::from("mappings.json")),
+            settings: Settings {
+                nvt_client: nvt_client?,
+                projects,
+                project_defaults,
+                archive,
+                orphans,
+                budget,
+                sync_tasks: self.tasks.enabled.unwrap_or(false),
+                sync_users: self.users.enabled.unwrap_or(false),
+                crew,
+                time_entries,
+            },
+            schedule,
+            webhook,
+            full_sync_every,
+        })
+    }
+}
+
+/// Builds project rules, reporting invalid ones. Unnamed rules are named after their position.
+fn project_rules(
+    raw: Vec<RawProjectRule>,
+    key: &str,
+    errors: &mut Vec<String>,
+) -> Vec<ProjectRule> {
+    let mut rules = vec![];
+
+    for (i, raw) in raw.into_iter().enumerate() {
+        let name = raw.name.unwrap_or(format!("{}[{}]", key, i));
+
+        let name_regex = match raw.name_regex.as_deref().map(Regex::new) {
+            Some(Ok(x)) => Some(x),
+            Some(Err(e)) => {
+                errors.push(format!("{}: invalid name_regex: {}", name, e));
+                continue;
+            }
+            None => None,
+        };
+
+        let rule = ProjectRule {
+            customers: raw.customers,
+            name_regex,
+            tags: raw.tags.iter().map(|x| x.trim().to_lowercase()).collect(),
+            statuses: raw
+                .statuses
+                .iter()
+                .map(|x| x.trim().to_lowercase())
+                .collect(),
+            template_subproject: raw.template_subproject,
+            ends_before: raw.ends_before,
+            starts_after: raw.starts_after,
+            name,
+        };
+
+        if rule.is_empty() {
+            errors.push(format!(
+                "{}: has no conditions, so it matches every project",
+                rule.name
+            ));
+            continue;
+        }
+
+        rules.push(rule);
+    }
+
+    rules
+}
+
+/// Environment variables, read once so settings can be checked against any environment.
+struct Env(HashMap<String, String>);
+
+impl Env {
+    fn from_process() -> Self {
+        Self(
+            std::env::vars_os()
+                .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+                .collect(),
+        )
+    }
+
+    /// Reads an environment variable, treating an empty value as unset.
+    fn var(&self, key: &str) -> Option<String> {
+        self.0.get(key).filter(|x| !x.trim().is_empty()).cloned()
+    }
+
+    /// Overrides a key with an environment variable, if it is set.
+    fn string(&self, target: &mut Option<String>, key: &str) {
+        if let Some(value) = self.var(key) {
+            *target = Some(value);
+        }
+    }
+
+    /// Overrides a key with a parsed environment variable, if it is set.
+    fn parse<T: FromStr>(&self, target: &mut Option<T>, key: &str, errors: &mut Vec<String>) {
+        let Some(value) = self.var(key) else {
+            return;
+        };
+
+        match value.trim().parse::<T>() {
+            Ok(x) => *target = Some(x),
+            Err(_) => errors.push(format!("{} has an invalid value: {:?}", key, value)),
+        }
+    }
+
+    /// Overrides a key with a comma separated list of IDs, if it is set.
+    fn ids(&self, target: &mut Option<Vec<i64>>, key: &str, errors: &mut Vec<String>) {
+        let Some(value) = self.var(key) else {
+            return;
+        };
+
+        match value
+            .split(',')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(i64::from_str)
+            .collect::<Result<Vec<i64>, _>>()
+        {
+            Ok(x) => *target = Some(x),
+            Err(_) => errors.push(format!("{} is not a comma separated list of numbers", key)),
+        }
+    }
+}
+
+/// Treats a blank value, e.g. `token = ""` left in from the example, as missing.
+fn non_blank(value: Option<String>) -> Option<String> {
+    value.filter(|x| !x.trim().is_empty())
+}
+
+fn required<T>(value: Option<T>, key: &str, env: &str, errors: &mut Vec<String>) -> Option<T> {
+    if value.is_none() {
+        errors.push(format!(
+            "{} is missing, set it in the config file or {}",
+            key, env
+        ));
+    }
+
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUIRED: &str = r#"
+        [harvest]
+        token = "file-token"
+        account_id = "123"
+        user_agent = "Test (test@example.com)"
+        nvt_client = 99
+
+        [rentman]
+        token = "rentman-token"
+    "#;
+
+    /// Loads a config file with only these environment variables set.
+    fn load(toml: &str, env: &[(&str, &str)]) -> Result<Config, String> {
+        let raw = toml::from_str::<RawConfig>(toml).unwrap();
+        let env = Env(env
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect());
+
+        Config::from_raw(raw, &env).map_err(|e| e.to_string())
+    }
+
+    fn excluded_customers(config: &Config) -> Vec<i64> {
+        config
+            .settings
+            .projects
+            .exclude
+            .iter()
+            .find(|x| x.name == "filters.excluded_customers")
+            .map(|x| x.customers.clone())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn environment_overrides_file() {
+        let config = load(
+            REQUIRED,
+            &[("HARVEST_TOKEN", "env-token"), ("HARVEST_ACCOUNT_ID", " ")],
+        )
+        .unwrap();
+
+        assert_eq!(config.harvest.token, "env-token");
+        // Empty variables are ignored
+        assert_eq!(config.harvest.account_id, "123");
+        assert_eq!(config.settings.nvt_client, 99);
+    }
+
+    #[test]
+    fn environment_alone_is_enough() {
+        let config = load(
+            "",
+            &[
+                ("HARVEST_TOKEN", "t"),
+                ("HARVEST_ACCOUNT_ID", "1"),
+                ("HARVEST_USER_AGENT", "x"),
+                ("HARVEST_NVT_CLIENT", "5"),
+                ("RENTMAN_TOKEN", "r"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(config.settings.nvt_client, 5);
+        assert!(config.schedule.is_none());
+        assert!(config.webhook.is_none());
+    }
+
+    #[test]
+    fn btdb_id_is_added_to_excluded_customers() {
+        let toml = format!("{}\n[filters]\nexcluded_customers = [1]", REQUIRED);
+
+        let config = load(&toml, &[("RENTMAN_BTDB_ID", "2")]).unwrap();
+        assert_eq!(excluded_customers(&config), vec![1, 2]);
+
+        let config = load(
+            &toml,
+            &[
+                ("RENTMAN_EXCLUDED_CUSTOMERS", "3, 4"),
+                ("RENTMAN_BTDB_ID", "2"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(excluded_customers(&config), vec![3, 4, 2]);
+    }
+
+    #[test]
+    fn reports_missing_and_blank_keys() {
+        let error = load("", &[]).err().unwrap();
+
+        for key in [
+            "harvest.token is missing, set it in the config file or HARVEST_TOKEN",
+            "harvest.account_id is missing",
+            "harvest.user_agent is missing",
+            "harvest.nvt_client is missing",
+            "rentman.token is missing",
+        ] {
+            assert!(error.contains(key), "{} not in {}", key, error);
+        }
+
+        let blank = REQUIRED
+            .replace("\"file-token\"", "\"\"")
+            .replace("99", "0");
+        let error = load(&blank, &[]).err().unwrap();
+
+        assert!(error.contains("harvest.token is missing"), "{}", error);
+        assert!(error.contains("harvest.nvt_client must be"), "{}", error);
+    }
+
+    #[test]
+    fn example_config_needs_the_required_keys() {
+        let error = load(include_str!("../config.example.toml"), &[])
+            .err()
+            .unwrap();
+
+        assert!(error.contains("harvest.token is missing"), "{}", error);
+        assert!(error.contains("harvest.nvt_client is missing"), "{}", error);
+        assert!(error.contains("rentman.token is missing"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_empty_rules_and_bad_regexes() {
+        let toml = format!(
+            "{}\n{}",
+            REQUIRED,
+            r#"
+            [[projects.exclude]]
+            name = "leeg"
+
+            [[projects.include]]
+            name_regex = "(unclosed"
+            "#
+        );
+
+        let error = load(&toml, &[]).err().unwrap();
+
+        assert!(
+            error.contains("leeg: has no conditions, so it matches every project"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("projects.include[0]: invalid name_regex"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn schedule_takes_interval_or_cron() {
+        let toml = format!(
+            "{}\n[schedule]\ninterval = \"15m\"\ncron = \"0 * * * *\"",
+            REQUIRED
+        );
+
+        let error = load(&toml, &[]).err().unwrap();
+        assert!(
+            error.contains("schedule.interval and schedule.cron can't both be set"),
+            "{}",
+            error
+        );
+
+        // A variable replaces the whole schedule from the file
+        let config = load(&toml, &[("SYNC_CRON", "0 * * * *")]).unwrap();
+        assert!(config.schedule.is_some());
+    }
+}
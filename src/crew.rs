@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use crate::{harvest, rentman};
+
+/// Rentman crew member ID by Harvest user ID. Users are matched by email, and `explicit`
+/// covers users whose email differs between the two.
+pub fn crew_by_user(
+    users: &[harvest::User],
+    crew: &[rentman::CrewData],
+    explicit: &HashMap<i64, i64>,
+) -> HashMap<i64, i64> {
+    let crew_by_email: HashMap<String, i64> = crew
+        .iter()
+        .filter_map(|x| Some((x.email.as_ref()?.trim().to_lowercase(), x.id)))
+        .collect();
+
+    users
+        .iter()
+        .filter_map(|x| Some((x.id, *crew_by_email.get(&x.email.trim().to_lowercase())?)))
+        .chain(explicit.iter().map(|(k, v)| (*k, *v)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{crew, user};
+
+    #[test]
+    fn matches_users_by_email() {
+        let users = [user(50, "Anna", "Jansen"), user(51, "Bram", "Visser")];
+        let crew = [
+            crew(60, "Anna", Some(" ANNA@example.com ")),
+            crew(61, "Bram", Some("b.visser@example.com")),
+            crew(62, "Cees", None),
+        ];
+
+        assert_eq!(
+            crew_by_user(&users, &crew, &HashMap::new()),
+            HashMap::from([(50, 60)])
+        );
+    }
+
+    #[test]
+    fn explicit_ids_override_email() {
+        let users = [user(50, "Anna", "Jansen"), user(51, "Bram", "Visser")];
+        let crew = [
+            crew(60, "Anna", Some("anna@example.com")),
+            crew(61, "Bram", Some("b.visser@example.com")),
+            crew(63, "Anna", None),
+        ];
+
+        assert_eq!(
+            crew_by_user(&users, &crew, &HashMap::from([(50, 63), (51, 61)])),
+            HashMap::from([(50, 63), (51, 61)])
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncError {
+    /// The request could not be sent or the response could not be read.
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The API answered with a non-2xx status.
+    #[error("{url} returned {status}: {body}")]
+    Api {
+        url: String,
+        status: StatusCode,
+        body: String,
+    },
+
+    /// Harvest rejected the request, e.g. a 422 for a duplicate project code.
+    #[error("{url} returned {status}: {error}")]
+    Harvest {
+        url: String,
+        status: StatusCode,
+        error: crate::harvest::ApiError,
+    },
+
+    /// The response body is not the JSON we expected.
+    #[error("Could not decode response from {url}: {source}")]
+    Decode {
+        url: String,
+        source: serde_json::Error,
+    },
+
+    /// A record references another record that can't be found.
+    #[error("Missing mapping: {0}")]
+    MissingMapping(String),
+
+    /// The ID mapping store can't be read or written.
+    #[error("Mapping store error: {0}")]
+    Store(String),
+
+    #[error("Configuration error: {0}")]
+    Config(String),
+}
+
+/// Returns the response if its status is 2xx, otherwise an `Api` error with the body.
+pub async fn check(res: Response) -> Result<Response, SyncError> {
+    let status = res.status();
+
+    if status.is_success() {
+        return Ok(res);
+    }
+
+    Err(SyncError::Api {
+        url: res.url().to_string(),
+        status,
+        body: res.text().await?,
+    })
+}
+
+/// Checks the status of the response and decodes its JSON body.
+pub async fn json<T: DeserializeOwned>(res: Response) -> Result<T, SyncError> {
+    let res = check(res).await?;
+    let url = res.url().to_string();
+    let body = res.text().await?;
+
+    serde_json::from_str(&body).map_err(|source| SyncError::Decode { url, source })
+}
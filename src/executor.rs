@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+
+use crate::error::SyncError;
+use crate::harvest::{self, HarvestClient};
+use crate::mapping::MappingStore;
+use crate::plan::{Action, ClientChange, ClientRef, ProjectChange, ProjectRef, SyncPlan, TaskRef};
+use crate::rules::BudgetSource;
+use crate::schedule::Shutdown;
+use crate::sync::Targets;
+
+/// Changes that failed to apply.
+#[derive(Debug, Default)]
+pub struct Failures {
+    pub count: usize,
+    /// The Rentman records of the failed changes, to sync again.
+    pub retry: Targets,
+}
+
+/// Applies a plan to Harvest, or only prints it when `dry_run` is set.
+///
+/// A failing action is logged and skipped, so the rest of the plan is still applied.
+/// Created records are added to `mappings`, which is saved after every create.
+/// When a shutdown is requested, the remaining actions are left for the next sync.
+pub async fn apply(
+    harvest: &HarvestClient,
+    mappings: &mut MappingStore,
+    plan: SyncPlan,
+    dry_run: bool,
+    shutdown: &Shutdown,
+) -> Failures {
+    for skipped in &plan.skipped {
+        println!("{}", skipped);
+    }
+
+    for warning in &plan.warnings {
+        println!("{}", warning);
+    }
+
+    let mut failures = Failures::default();
+    // Harvest task IDs by lowercase name, for tasks created by this plan
+    let mut created_tasks: HashMap<String, i64> = HashMap::new();
+    let total = plan.actions.len();
+
+    for (i, action) in plan.actions.into_iter().enumerate() {
+        if shutdown.is_requested() {
+            println!(
+                "Shutdown requested, skipping {} remaining changes",
+                total - i
+            );
+            break;
+        }
+
+        if dry_run {
+            println!("[dry-run] {}", action);
+            continue;
+        }
+
+        println!("{}", action);
+
+        // Known before the action runs, as a create adds the record to the mappings
+        let record = rentman_record(mappings, &action);
+
+        if let Err(e) = apply_action(harvest, mappings, &mut created_tasks, action).await {
+            eprintln!("Failed: {}", e);
+            failures.count += 1;
+
+            match record {
+                Some(Record::Contact(id)) => {
+                    failures.retry.contacts.insert(id);
+                }
+                Some(Record::Project(id)) => {
+                    failures.retry.projects.insert(id);
+                }
+                None => {}
+            }
+        }
+    }
+
+    failures
+}
+
+/// A Rentman record, by ID.
+#[derive(Debug, PartialEq)]
+enum Record {
+    Contact(i64),
+    Project(i64),
+}
+
+/// The Rentman record an action syncs. Tasks belong to no record, but the assignments that
+/// need them fail with them.
+fn rentman_record(mappings: &MappingStore, action: &Action) -> Option<Record> {
+    let project = |harvest_id: &i64| {
+        mappings
+            .rentman_projects_by_project()
+            .get(harvest_id)
+            .copied()
+            .map(Record::Project)
+    };
+
+    let project_ref = |project_ref: &ProjectRef| match project_ref {
+        ProjectRef::Existing(id) => project(id),
+        ProjectRef::New(rentman_id) => Some(Record::Project(*rentman_id)),
+    };
+
+    match action {
+        Action::CreateClient { rentman_id, .. } => Some(Record::Contact(*rentman_id)),
+        Action::UpdateClient { id, .. } => mappings
+            .contacts_by_client()
+            .get(id)
+            .copied()
+            .map(Record::Contact),
+        Action::CreateProject { rentman_id, .. } => Some(Record::Project(*rentman_id)),
+        Action::UpdateProject { id, .. } => project(id),
+        Action::CreateTask { .. } | Action::ReactivateTask { .. } => None,
+        Action::AssignTask { project, .. } | Action::AssignUser { project, .. } => {
+            project_ref(project)
+        }
+        Action::SetTaskActive { project_id, .. } | Action::SetUserActive { project_id, .. } => {
+            project(project_id)
+        }
+    }
+}
+
+async fn apply_action(
+    harvest: &HarvestClient,
+    mappings: &mut MappingStore,
+    created_tasks: &mut HashMap<String, i64>,
+    action: Action,
+) -> Result<(), SyncError> {
+    match action {
+        Action::CreateClient { rentman_id, name } => {
+            let client = harvest
+                .create_client(harvest::CreateClient {
+                    name,
+                    address: None,
+                })
+                .await?;
+
+            mappings.clients.insert(rentman_id, client.id);
+            mappings
+                .written_clients
+                .insert(client.id, client.updated_at);
+            mappings.save()?;
+        }
+        Action::UpdateClient { id, changes, .. } => {
+            let mut data = harvest::UpdateClient {
+                name: None,
+                address: None,
+                is_active: None,
+            };
+
+            for change in changes {
+                match change {
+                    ClientChange::Rename { to } => data.name = Some(to),
+                    ClientChange::Archive { .. } => data.is_active = Some(false),
+                }
+            }
+
+            let client = harvest.update_client(id, data).await?;
+
+            mappings
+                .written_clients
+                .insert(client.id, client.updated_at);
+        }
+        Action::CreateProject {
+            rentman_id,
+            name,
+            code,
+            client,
+            is_active,
+            defaults,
+            budget,
+            starts_on,
+            ends_on,
+        } => {
+            let client_id = resolve_client(mappings, client)?;
+
+            let mut data = harvest::CreateProject {
+                is_active,
+                name,
+                client_id,
+                code,
+                notes: None,
+                bill_by: defaults.bill_by,
+                budget_by: defaults.budget_by,
+                is_billable: defaults.is_billable,
+                budget: None,
+                cost_budget: None,
+                notify_when_over_budget: None,
+                over_budget_notification_percentage: None,
+                starts_on,
+                ends_on,
+            };
+
+            if let Some(budget) = budget {
+                data.budget_by = budget.budget_by().to_string();
+
+                match budget.source {
+                    _ if budget.is_empty() => {}
+                    BudgetSource::Hours => data.budget = Some(budget.amount),
+                    BudgetSource::Fees => data.cost_budget = Some(budget.amount),
+                }
+
+                if let Some(percentage) = budget.notify_at.filter(|_| !budget.is_empty()) {
+                    data.notify_when_over_budget = Some(true);
+                    data.over_budget_notification_percentage = Some(percentage);
+                }
+            }
+
+            let project = harvest.create_project(data).await?;
+
+            mappings.projects.insert(rentman_id, project.id);
+
+            if let Some(updated_at) = project.updated_at {
+                mappings.written_projects.insert(project.id, updated_at);
+            }
+
+            mappings.save()?;
+        }
+        Action::UpdateProject { id, changes, .. } => {
+            let mut data = harvest::UpdateProject::default();
+
+            for change in changes {
+                match change {
+                    ProjectChange::Rename { to } => data.name = Some(to),
+                    ProjectChange::Recode { to, .. } => data.code = Some(to),
+                    ProjectChange::Reassign { to, .. } => {
+                        data.client_id = Some(resolve_client(mappings, to)?)
+                    }
+                    ProjectChange::SetActive { is_active, .. } => data.is_active = Some(is_active),
+                    ProjectChange::Reschedule { starts_on, ends_on } => {
+                        data.starts_on = starts_on;
+                        data.ends_on = ends_on;
+                    }
+                    ProjectChange::SetBudget { to } => {
+                        data.budget_by = Some(to.budget_by().to_string());
+
+                        // Clears the amount of the other source too, e.g. when switching to hours
+                        data.budget = Some(None);
+                        data.cost_budget = Some(None);
+
+                        if to.is_empty() {
+                            continue;
+                        }
+
+                        match to.source {
+                            BudgetSource::Hours => data.budget = Some(Some(to.amount)),
+                            BudgetSource::Fees => data.cost_budget = Some(Some(to.amount)),
+                        }
+
+                        if let Some(percentage) = to.notify_at {
+                            data.notify_when_over_budget = Some(true);
+                            data.over_budget_notification_percentage = Some(percentage);
+                        }
+                    }
+                }
+            }
+
+            let project = harvest.update_project(id, data).await?;
+
+            if let Some(updated_at) = project.updated_at {
+                mappings.written_projects.insert(project.id, updated_at);
+            }
+        }
+        Action::CreateTask { name } => {
+            let task = harvest.create_task(harvest::CreateTask { name }).await?;
+
+            created_tasks.insert(task.name.to_lowercase(), task.id);
+        }
+        Action::ReactivateTask { id, .. } => {
+            harvest
+                .update_task(id, harvest::UpdateTask { is_active: true })
+                .await?;
+        }
+        Action::AssignTask {
+            project,
+            task,
+            task_name,
+            ..
+        } => {
+            let project_id = resolve_project(mappings, project)?;
+
+            let task_id = match task {
+                TaskRef::Existing(id) => id,
+                TaskRef::New(name) => created_tasks
+                    .get(&name.to_lowercase())
+                    .copied()
+                    .ok_or_else(|| {
+                        SyncError::MissingMapping(format!(
+                            "no Harvest task was created for \"{}\"",
+                            task_name
+                        ))
+                    })?,
+            };
+
+            let assignment = harvest
+                .create_task_assignment(
+                    project_id,
+                    harvest::CreateTaskAssignment {
+                        task_id,
+                        is_active: true,
+                    },
+                )
+                .await?;
+
+            mappings.task_assignments.insert(assignment.id);
+            mappings.save()?;
+        }
+        Action::SetTaskActive {
+            project_id,
+            id,
+            is_active,
+            ..
+        } => {
+            harvest
+                .update_task_assignment(project_id, id, harvest::UpdateTaskAssignment { is_active })
+                .await?;
+        }
+        Action::AssignUser {
+            project, user_id, ..
+        } => {
+            let project_id = resolve_project(mappings, project)?;
+
+            let assignment = harvest
+                .create_user_assignment(project_id, harvest::CreateUserAssignment { user_id })
+                .await?;
+
+            mappings.user_assignments.insert(assignment.id);
+            mappings.save()?;
+        }
+        Action::SetUserActive {
+            project_id,
+            id,
+            is_active,
+            ..
+        } => {
+            harvest
+                .update_user_assignment(project_id, id, harvest::UpdateUserAssignment { is_active })
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_client(mappings: &MappingStore, client: ClientRef) -> Result<i64, SyncError> {
+    match client {
+        ClientRef::Existing(id) => Ok(id),
+        ClientRef::New(rentman_id) => mappings.clients.get(&rentman_id).copied().ok_or_else(|| {
+            SyncError::MissingMapping(format!(
+                "no Harvest client was created for Rentman contact {}",
+                rentman_id
+            ))
+        }),
+    }
+}
+
+fn resolve_project(mappings: &MappingStore, project: ProjectRef) -> Result<i64, SyncError> {
+    match project {
+        ProjectRef::Existing(id) => Ok(id),
+        ProjectRef::New(rentman_id) => {
+            mappings.projects.get(&rentman_id).copied().ok_or_else(|| {
+                SyncError::MissingMapping(format!(
+                    "no Harvest project was created for Rentman project {}",
+                    rentman_id
+                ))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_actions_are_traced_to_their_rentman_record() {
+        let mut mappings = MappingStore::default();
+        mappings.clients.insert(7, 100);
+        mappings.projects.insert(5, 200);
+
+        let record = |action: Action| rentman_record(&mappings, &action);
+
+        assert_eq!(
+            record(Action::UpdateClient {
+                id: 100,
+                name: "Acme".to_string(),
+                changes: vec![],
+            }),
+            Some(Record::Contact(7))
+        );
+        assert_eq!(
+            record(Action::UpdateProject {
+                id: 200,
+                name: "Job".to_string(),
+                changes: vec![],
+            }),
+            Some(Record::Project(5))
+        );
+        assert_eq!(
+            record(Action::AssignTask {
+                project: ProjectRef::New(6),
+                project_name: "New job".to_string(),
+                task: TaskRef::New("Opbouw".to_string()),
+                task_name: "Opbouw".to_string(),
+            }),
+            Some(Record::Project(6))
+        );
+        assert_eq!(
+            record(Action::UpdateProject {
+                id: 201,
+                name: "Made by hand".to_string(),
+                changes: vec![],
+            }),
+            None
+        );
+        assert_eq!(
+            record(Action::CreateTask {
+                name: "Opbouw".to_string(),
+            }),
+            None
+        );
+    }
+}
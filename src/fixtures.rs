@@ -0,0 +1,169 @@
+//! Harvest and Rentman records for unit tests, filled in the way the APIs return them.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::json;
+
+use crate::rentman::Status;
+use crate::{harvest, rentman};
+
+/// The time every test plans at.
+pub fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap()
+}
+
+pub fn contact(id: i64, name: &str) -> rentman::ContactsData {
+    rentman::ContactsData {
+        id,
+        created: String::new(),
+        modified: String::new(),
+        displayname: name.to_string(),
+        firstname: String::new(),
+        surfix: String::new(),
+        surname: String::new(),
+        code: String::new(),
+        name: name.to_string(),
+    }
+}
+
+/// A project without a plan period. Customer `0` means it has none.
+pub fn rentman_project(id: i64, name: &str, customer_id: i64) -> rentman::ProjectsData {
+    rentman::ProjectsData {
+        id,
+        displayname: name.to_string(),
+        customer: (customer_id != 0).then(|| format!("/contacts/{}", customer_id)),
+        customer_id,
+        name: name.to_string(),
+        number: 1000 + id,
+        tags: String::new(),
+        planperiod_start: None,
+        planperiod_end: None,
+        project_crew_price: 0.0,
+    }
+}
+
+/// Subproject `order` of a project, named after its day.
+pub fn subproject(project_id: i64, order: i64, status: Status) -> rentman::SubprojectData {
+    rentman::SubprojectData {
+        id: project_id * 100 + order,
+        displayname: format!("Dag {}", order),
+        project: Some(format!("/projects/{}", project_id)),
+        project_id,
+        order,
+        name: format!("Dag {}", order),
+        status,
+        is_template: false,
+    }
+}
+
+pub fn status(id: i64, name: &str) -> rentman::StatusesData {
+    rentman::StatusesData {
+        id,
+        displayname: name.to_string(),
+        name: name.to_lowercase(),
+    }
+}
+
+pub fn client(id: i64, name: &str) -> harvest::ClientData {
+    serde_json::from_value(json!({
+        "id": id,
+        "name": name,
+        "is_active": true,
+        "address": null,
+        "statement_key": "",
+        "created_at": "2025-01-01T00:00:00Z",
+        "updated_at": "2025-01-01T00:00:00Z",
+    }))
+    .unwrap()
+}
+
+/// An active project without budget or dates.
+pub fn harvest_project(id: i64, name: &str, code: &str, client_id: i64) -> harvest::Project {
+    serde_json::from_value(json!({
+        "id": id,
+        "name": name,
+        "code": code,
+        "is_active": true,
+        "notes": null,
+        "client": { "id": client_id, "name": "" },
+        "updated_at": "2025-01-01T00:00:00Z",
+    }))
+    .unwrap()
+}
+
+pub fn task(id: i64, name: &str, is_active: bool) -> harvest::Task {
+    harvest::Task {
+        id,
+        name: name.to_string(),
+        is_active,
+    }
+}
+
+pub fn task_assignment(
+    id: i64,
+    project_id: i64,
+    task: &harvest::Task,
+    is_active: bool,
+) -> harvest::TaskAssignment {
+    harvest::TaskAssignment {
+        id,
+        is_active,
+        project: harvest::Reference {
+            id: project_id,
+            name: String::new(),
+        },
+        task: harvest::Reference {
+            id: task.id,
+            name: task.name.clone(),
+        },
+    }
+}
+
+/// An active user whose email is `first@example.com`.
+pub fn user(id: i64, first_name: &str, last_name: &str) -> harvest::User {
+    harvest::User {
+        id,
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        email: format!("{}@example.com", first_name.to_lowercase()),
+        is_active: true,
+    }
+}
+
+pub fn crew(id: i64, name: &str, email: Option<&str>) -> rentman::CrewData {
+    rentman::CrewData {
+        id,
+        displayname: name.to_string(),
+        email: email.map(str::to_string),
+    }
+}
+
+/// Crew member `crew_id` planned on a function of the project.
+pub fn project_crew(id: i64, project_id: i64, crew_id: i64) -> rentman::ProjectCrewData {
+    rentman::ProjectCrewData {
+        id,
+        function: format!("/projectfunctions/{}", id),
+        crewmember: Some(format!("/crew/{}", crew_id)),
+        project_id,
+        crew_id: Some(crew_id),
+    }
+}
+
+pub fn user_assignment(
+    id: i64,
+    project_id: i64,
+    user: &harvest::User,
+    is_active: bool,
+) -> harvest::UserAssignment {
+    harvest::UserAssignment {
+        id,
+        is_active,
+        project: harvest::Reference {
+            id: project_id,
+            name: String::new(),
+        },
+        user: harvest::Reference {
+            id: user.id,
+            name: format!("{} {}", user.first_name, user.last_name),
+        },
+    }
+}
@@ -1,130 +1,389 @@
-use reqwest::Error;
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, USER_AGENT};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::error::{self, SyncError};
+use crate::http::{HttpClient, RateLimit};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.harvestapp.com/v2";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Harvest allows 100 requests per 15 seconds.
+const RATE_LIMIT: RateLimit = RateLimit {
+    max_requests: 100,
+    window: Duration::from_secs(15),
+};
 
 pub struct HarvestClient {
-    token: String,
-    pub account_id: String,
-    pub user_agent: String,
-    pub nvt_client: i64,
+    base_url: String,
+    /// Shared connection pool with the authentication headers set on every request.
+    http: HttpClient,
 }
 
 impl HarvestClient {
-    pub fn new(token: String, account_id: String, user_agent: String, nvt_client: i64) -> Self {
-        Self {
-            token,
-            account_id,
-            user_agent,
-            nvt_client,
-        }
+    pub fn new(token: &str, account_id: &str, user_agent: &str) -> Result<Self, SyncError> {
+        let mut authorization = header_value("token", &format!("Bearer {}", token))?;
+        authorization.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization);
+        headers.insert(
+            "Harvest-Account-Id",
+            header_value("account ID", account_id)?,
+        );
+        headers.insert(USER_AGENT, header_value("user agent", user_agent)?);
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            http: HttpClient::new(http, RATE_LIMIT),
+        })
     }
 
-    pub async fn get_clients(&self) -> Result<Clients, Error> {
-        let client = reqwest::Client::new();
-
-        let res = client
-            .get("https://api.harvestapp.com/v2/clients")
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .header("Harvest-Account-Id", &self.account_id)
-            .header("User-Agent", &self.user_agent)
-            .send()
-            .await?;
-
-        let json = res.json::<Clients>().await?;
+    /// Sends requests to another API root, e.g. a mock server or a recording proxy.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
 
-        // Pagination is not currently implemented
-        if json.total_entries >= json.per_page {
-            panic!("Not all clients are fetched");
-        }
+    /// Fetches all clients, or only those updated since `since`.
+    pub async fn get_clients(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<ClientData>, SyncError> {
+        self.get_all(&self.list_url("clients", since)).await
+    }
 
-        Ok(json)
+    /// Fetches one client, or `None` if it was deleted.
+    pub async fn get_client(&self, id: i64) -> Result<Option<ClientData>, SyncError> {
+        self.get_one(&format!("{}/clients/{}", self.base_url, id))
+            .await
     }
 
     pub async fn create_client(
         &self,
         create_client: CreateClient,
-    ) -> Result<reqwest::Response, Error> {
-        let client = reqwest::Client::new();
-
-        client
-            .post("https://api.harvestapp.com/v2/clients")
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .header("Harvest-Account-Id", &self.account_id)
-            .header("Content-Type", "application/json")
-            .header("User-Agent", &self.user_agent)
-            .json(&create_client)
-            .send()
-            .await
+    ) -> Result<ClientData, SyncError> {
+        let req = self
+            .http
+            .post(format!("{}/clients", self.base_url))
+            .json(&create_client);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
     }
 
     pub async fn update_client(
         &self,
         id: i64,
         data: UpdateClient,
-    ) -> Result<reqwest::Response, Error> {
-        let client = reqwest::Client::new();
-
-        client
-            .patch(format!("https://api.harvestapp.com/v2/clients/{}", id))
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .header("Harvest-Account-Id", &self.account_id)
-            .header("Content-Type", "application/json")
-            .header("User-Agent", &self.user_agent)
-            .json(&data)
-            .send()
+    ) -> Result<ClientData, SyncError> {
+        let req = self
+            .http
+            .patch(format!("{}/clients/{}", self.base_url, id))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
+    }
+
+    /// Fetches all projects, or only those updated since `since`.
+    pub async fn get_projects(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Project>, SyncError> {
+        self.get_all(&self.list_url("projects", since)).await
+    }
+
+    /// Fetches one project, or `None` if it was deleted.
+    pub async fn get_project(&self, id: i64) -> Result<Option<Project>, SyncError> {
+        self.get_one(&format!("{}/projects/{}", self.base_url, id))
             .await
     }
 
-    pub async fn get_projects(&self) -> Result<Projects, Error> {
-        let client = reqwest::Client::new();
+    pub async fn create_project(&self, data: CreateProject) -> Result<Project, SyncError> {
+        let req = self
+            .http
+            .post(format!("{}/projects", self.base_url))
+            .json(&data);
 
-        let res = client
-            .get("https://api.harvestapp.com/v2/projects")
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .header("Harvest-Account-Id", &self.account_id)
-            .header("User-Agent", &self.user_agent)
-            .send()
-            .await?;
+        let res = self.http.send(req).await?;
 
-        let json = res.json::<Projects>().await?;
+        read(res).await
+    }
+
+    pub async fn update_project(&self, id: i64, data: UpdateProject) -> Result<Project, SyncError> {
+        let req = self
+            .http
+            .patch(format!("{}/projects/{}", self.base_url, id))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
+    }
+
+    pub async fn get_tasks(&self) -> Result<Vec<Task>, SyncError> {
+        self.get_all(&format!("{}/tasks", self.base_url)).await
+    }
+
+    pub async fn create_task(&self, data: CreateTask) -> Result<Task, SyncError> {
+        let req = self
+            .http
+            .post(format!("{}/tasks", self.base_url))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
+    }
+
+    pub async fn update_task(&self, id: i64, data: UpdateTask) -> Result<Task, SyncError> {
+        let req = self
+            .http
+            .patch(format!("{}/tasks/{}", self.base_url, id))
+            .json(&data);
 
-        // Pagination is not currently implemented
-        if json.total_entries >= json.per_page {
-            panic!("Not all clients are fetched");
+        let res = self.http.send(req).await?;
+
+        read(res).await
+    }
+
+    /// Fetches the task assignments of all projects, or of one project only.
+    pub async fn get_task_assignments(
+        &self,
+        project_id: Option<i64>,
+    ) -> Result<Vec<TaskAssignment>, SyncError> {
+        match project_id {
+            Some(id) => {
+                self.get_all(&format!(
+                    "{}/projects/{}/task_assignments",
+                    self.base_url, id
+                ))
+                .await
+            }
+            None => {
+                self.get_all(&format!("{}/task_assignments", self.base_url))
+                    .await
+            }
         }
+    }
 
-        Ok(json)
+    pub async fn create_task_assignment(
+        &self,
+        project_id: i64,
+        data: CreateTaskAssignment,
+    ) -> Result<TaskAssignment, SyncError> {
+        let req = self
+            .http
+            .post(format!(
+                "{}/projects/{}/task_assignments",
+                self.base_url, project_id
+            ))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
     }
 
-    pub async fn create_project(&self, data: CreateProject) -> Result<reqwest::Response, Error> {
-        let client = reqwest::Client::new();
+    pub async fn update_task_assignment(
+        &self,
+        project_id: i64,
+        id: i64,
+        data: UpdateTaskAssignment,
+    ) -> Result<TaskAssignment, SyncError> {
+        let req = self
+            .http
+            .patch(format!(
+                "{}/projects/{}/task_assignments/{}",
+                self.base_url, project_id, id
+            ))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
+    }
 
-        client
-            .post("https://api.harvestapp.com/v2/projects")
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .header("Harvest-Account-Id", &self.account_id)
-            .header("Content-Type", "application/json")
-            .header("User-Agent", &self.user_agent)
-            .json(&data)
-            .send()
-            .await
+    /// Fetches the user assignments of all projects, or of one project only.
+    pub async fn get_user_assignments(
+        &self,
+        project_id: Option<i64>,
+    ) -> Result<Vec<UserAssignment>, SyncError> {
+        match project_id {
+            Some(id) => {
+                self.get_all(&format!(
+                    "{}/projects/{}/user_assignments",
+                    self.base_url, id
+                ))
+                .await
+            }
+            None => {
+                self.get_all(&format!("{}/user_assignments", self.base_url))
+                    .await
+            }
+        }
+    }
+
+    pub async fn create_user_assignment(
+        &self,
+        project_id: i64,
+        data: CreateUserAssignment,
+    ) -> Result<UserAssignment, SyncError> {
+        let req = self
+            .http
+            .post(format!(
+                "{}/projects/{}/user_assignments",
+                self.base_url, project_id
+            ))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
     }
 
-    pub async fn update_project(
+    pub async fn update_user_assignment(
         &self,
+        project_id: i64,
         id: i64,
-        data: UpdateProject,
-    ) -> Result<reqwest::Response, Error> {
-        let client = reqwest::Client::new();
-
-        client
-            .patch(format!("https://api.harvestapp.com/v2/projects/{}", id))
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .header("Harvest-Account-Id", &self.account_id)
-            .header("Content-Type", "application/json")
-            .header("User-Agent", &self.user_agent)
-            .json(&data)
-            .send()
-            .await
+        data: UpdateUserAssignment,
+    ) -> Result<UserAssignment, SyncError> {
+        let req = self
+            .http
+            .patch(format!(
+                "{}/projects/{}/user_assignments/{}",
+                self.base_url, project_id, id
+            ))
+            .json(&data);
+
+        let res = self.http.send(req).await?;
+
+        read(res).await
+    }
+
+    /// Fetches the time entries spent from `from` to `to`, inclusive, optionally of one project only.
+    pub async fn get_time_entries(
+        &self,
+        from: NaiveDate,
+        to: NaiveDate,
+        project_id: Option<i64>,
+    ) -> Result<Vec<TimeEntry>, SyncError> {
+        let mut url = format!("{}/time_entries?from={}&to={}", self.base_url, from, to);
+
+        if let Some(project_id) = project_id {
+            url.push_str(&format!("&project_id={}", project_id));
+        }
+
+        self.get_all(&url).await
+    }
+
+    pub async fn get_users(&self) -> Result<Vec<User>, SyncError> {
+        self.get_all(&format!("{}/users", self.base_url)).await
+    }
+
+    fn list_url(&self, path: &str, since: Option<DateTime<Utc>>) -> String {
+        match since {
+            Some(since) => format!(
+                "{}/{}?updated_since={}",
+                self.base_url,
+                path,
+                since.to_rfc3339_opts(SecondsFormat::Secs, true)
+            ),
+            None => format!("{}/{}", self.base_url, path),
+        }
+    }
+
+    async fn get_one<T: DeserializeOwned>(&self, url: &str) -> Result<Option<T>, SyncError> {
+        let res = self.http.send(self.http.get(url)).await?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        read(res).await.map(Some)
+    }
+
+    /// Fetches the records on every page of a list endpoint by following `links.next`.
+    async fn get_all<T: DeserializeOwned>(&self, url: &str) -> Result<Vec<T>, SyncError> {
+        let mut next = Some(url.to_string());
+        let mut records = vec![];
+
+        while let Some(url) = next {
+            let res = self.http.send(self.http.get(url)).await?;
+
+            let page = read::<Page<T>>(res).await?;
+
+            next = page.links.next;
+            records.extend(page.records.into_values().flatten());
+        }
+
+        Ok(records)
+    }
+}
+
+fn header_value(name: &str, value: &str) -> Result<HeaderValue, SyncError> {
+    HeaderValue::from_str(value).map_err(|_| SyncError::Config(format!("Invalid Harvest {}", name)))
+}
+
+/// Decodes a successful response, or turns Harvest's error body into a `SyncError::Harvest`.
+async fn read<T: DeserializeOwned>(res: reqwest::Response) -> Result<T, SyncError> {
+    let status = res.status();
+
+    if status.is_success() {
+        return error::json(res).await;
+    }
+
+    Err(error(res).await)
+}
+
+/// Turns an unsuccessful response into a `SyncError::Harvest`, or `SyncError::Api` when the
+/// body isn't a Harvest error.
+async fn error(res: reqwest::Response) -> SyncError {
+    let status = res.status();
+    let url = res.url().to_string();
+
+    let body = match res.text().await {
+        Ok(x) => x,
+        Err(e) => return e.into(),
+    };
+
+    match serde_json::from_str::<ApiError>(&body) {
+        Ok(error) => SyncError::Harvest { url, status, error },
+        Err(_) => SyncError::Api { url, status, body },
+    }
+}
+
+/// Error body returned by Harvest, either a validation message or an OAuth-style error.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApiError {
+    pub message: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.message, &self.error, &self.error_description) {
+            (Some(message), _, _) => write!(f, "{}", message),
+            (None, Some(error), Some(description)) => write!(f, "{}: {}", error, description),
+            (None, Some(error), None) => write!(f, "{}", error),
+            (None, None, _) => write!(f, "unknown error"),
+        }
     }
 }
 
@@ -133,29 +392,18 @@ impl HarvestClient {
 #[derive(Serialize, Deserialize)]
 pub struct CreateClient {
     pub name: String,
-    /// ookwel Rentman ID
-    pub address: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct UpdateClient {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
-    /// ookwel Rentman ID
     #[serde(skip_serializing_if = "Option::is_none")]
     pub address: Option<String>,
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Clients {
-    pub clients: Vec<ClientData>,
-    pub per_page: i64,
-    pub total_pages: i64,
-    pub total_entries: i64,
-    pub next_page: Option<serde_json::Value>,
-    pub previous_page: Option<serde_json::Value>,
-    pub page: i64,
-    pub links: Links,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -163,18 +411,34 @@ pub struct ClientData {
     pub id: i64,
     pub name: String,
     pub is_active: bool,
-    /// ookwel Rentman ID
+    /// Held the Rentman contact ID before the mapping store, see `MappingStore::bootstrap`.
     pub address: Option<String>,
     pub statement_key: String,
     pub created_at: String,
     pub updated_at: String,
 }
 
+/// A page of a Harvest list response. The records are under a key named after the endpoint,
+/// e.g. `clients`. The pagination fields are listed so they aren't taken for records.
+#[derive(Deserialize)]
+#[allow(dead_code)]
+struct Page<T> {
+    #[serde(flatten)]
+    records: HashMap<String, Vec<T>>,
+    per_page: i64,
+    total_pages: i64,
+    total_entries: i64,
+    next_page: Option<serde_json::Value>,
+    previous_page: Option<serde_json::Value>,
+    page: i64,
+    links: Links,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Links {
     pub first: String,
-    pub next: Option<serde_json::Value>,
-    pub previous: Option<serde_json::Value>,
+    pub next: Option<String>,
+    pub previous: Option<String>,
     pub last: String,
 }
 
@@ -183,7 +447,8 @@ pub struct Links {
 pub struct CreateProject {
     pub client_id: i64,
     pub name: String,
-    pub notes: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
     pub code: String,
     pub is_active: bool,
     #[serde(default = "is_billable")]
@@ -192,10 +457,24 @@ pub struct CreateProject {
     pub bill_by: String,
     #[serde(default = "budget_by")]
     pub budget_by: String,
+    /// Hours, when budgeting by `project`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget: Option<f64>,
+    /// Fees, when budgeting by `project_cost`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_budget: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify_when_over_budget: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub over_budget_notification_percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_on: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_on: Option<NaiveDate>,
 }
 
 // projects types
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct UpdateProject {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub client_id: Option<i64>,
@@ -207,6 +486,21 @@ pub struct UpdateProject {
     pub code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_by: Option<String>,
+    /// `Some(None)` clears the budget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost_budget: Option<Option<f64>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify_when_over_budget: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub over_budget_notification_percentage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub starts_on: Option<NaiveDate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ends_on: Option<NaiveDate>,
 }
 
 fn default_bill_by() -> String {
@@ -221,26 +515,31 @@ fn is_billable() -> bool {
     true
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Projects {
-    pub projects: Vec<Project>,
-    pub per_page: i64,
-    pub total_pages: i64,
-    pub total_entries: i64,
-    pub next_page: Option<serde_json::Value>,
-    pub previous_page: Option<serde_json::Value>,
-    pub page: i64,
-    pub links: Links,
-}
-
 #[derive(Serialize, Deserialize)]
 pub struct Project {
     pub id: i64,
     pub name: String,
     pub code: Option<String>,
     pub is_active: bool,
+    /// Held the Rentman project ID before the mapping store, see `MappingStore::bootstrap`.
     pub notes: Option<String>,
     pub client: ProjectClient,
+    #[serde(default)]
+    pub budget_by: Option<String>,
+    #[serde(default)]
+    pub budget: Option<f64>,
+    #[serde(default)]
+    pub cost_budget: Option<f64>,
+    #[serde(default)]
+    pub notify_when_over_budget: bool,
+    #[serde(default)]
+    pub over_budget_notification_percentage: Option<f64>,
+    #[serde(default)]
+    pub starts_on: Option<NaiveDate>,
+    #[serde(default)]
+    pub ends_on: Option<NaiveDate>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -248,3 +547,161 @@ pub struct ProjectClient {
     pub id: i64,
     pub name: String,
 }
+
+// task types
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateTask {
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Task {
+    pub id: i64,
+    pub name: String,
+    pub is_active: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateTask {
+    pub is_active: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateTaskAssignment {
+    pub task_id: i64,
+    pub is_active: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateTaskAssignment {
+    pub is_active: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TaskAssignment {
+    pub id: i64,
+    pub is_active: bool,
+    pub project: Reference,
+    pub task: Reference,
+}
+
+// user assignment types
+
+#[derive(Serialize, Deserialize)]
+pub struct CreateUserAssignment {
+    pub user_id: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UpdateUserAssignment {
+    pub is_active: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserAssignment {
+    pub id: i64,
+    pub is_active: bool,
+    pub project: Reference,
+    pub user: Reference,
+}
+
+// time entry types
+
+#[derive(Serialize, Deserialize)]
+pub struct TimeEntry {
+    pub id: i64,
+    pub spent_date: NaiveDate,
+    pub hours: f64,
+    pub notes: Option<String>,
+    /// The timer is still running, so `hours` will change.
+    pub is_running: bool,
+    pub updated_at: String,
+    pub user: Reference,
+    pub project: Reference,
+    pub task: Reference,
+}
+
+/// A record embedded in another one.
+#[derive(Serialize, Deserialize)]
+pub struct Reference {
+    pub id: i64,
+    pub name: String,
+}
+
+// user types
+
+#[derive(Serialize, Deserialize)]
+pub struct User {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: String,
+    pub email: String,
+    pub is_active: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn page_keeps_records_apart_from_pagination_fields() {
+        let page: Page<Task> = serde_json::from_value(json!({
+            "tasks": [
+                { "id": 1, "name": "Opbouw", "is_active": true },
+                { "id": 2, "name": "Afbouw", "is_active": false },
+            ],
+            "per_page": 2,
+            "total_pages": 2,
+            "total_entries": 3,
+            "next_page": 2,
+            "previous_page": null,
+            "page": 1,
+            "links": {
+                "first": "https://api.harvestapp.com/v2/tasks?page=1&per_page=2",
+                "next": "https://api.harvestapp.com/v2/tasks?page=2&per_page=2",
+                "previous": null,
+                "last": "https://api.harvestapp.com/v2/tasks?page=2&per_page=2",
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(page.records.len(), 1);
+        assert_eq!(
+            page.records["tasks"]
+                .iter()
+                .map(|x| x.id)
+                .collect::<Vec<i64>>(),
+            vec![1, 2]
+        );
+        assert_eq!(
+            page.links.next.as_deref(),
+            Some("https://api.harvestapp.com/v2/tasks?page=2&per_page=2")
+        );
+    }
+
+    #[test]
+    fn page_without_records_is_empty() {
+        let page: Page<Task> = serde_json::from_value(json!({
+            "tasks": [],
+            "per_page": 2000,
+            "total_pages": 0,
+            "total_entries": 0,
+            "next_page": null,
+            "previous_page": null,
+            "page": 1,
+            "links": {
+                "first": "https://api.harvestapp.com/v2/tasks?page=1&per_page=2000",
+                "next": null,
+                "previous": null,
+                "last": "https://api.harvestapp.com/v2/tasks?page=1&per_page=2000",
+            },
+        }))
+        .unwrap();
+
+        assert!(page.records.into_values().flatten().next().is_none());
+        assert!(page.links.next.is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::{header::RETRY_AFTER, Method, RequestBuilder, Response, StatusCode};
+
+use crate::error::SyncError;
+
+/// Number of times a request is retried before its error is returned.
+const MAX_RETRIES: u32 = 5;
+const BASE_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Request budget of an API: at most `max_requests` per `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub max_requests: usize,
+    pub window: Duration,
+}
+
+/// A `reqwest::Client` that paces requests to stay within a rate limit and retries
+/// throttled, failed and 5xx requests with exponential backoff.
+pub struct HttpClient {
+    client: reqwest::Client,
+    limit: RateLimit,
+    /// Send times of the requests in the current window.
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+impl HttpClient {
+    pub fn new(client: reqwest::Client, limit: RateLimit) -> Self {
+        Self {
+            client,
+            limit,
+            sent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    pub fn patch(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.patch(url)
+    }
+
+    pub fn put(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.put(url)
+    }
+
+    pub fn delete(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.delete(url)
+    }
+
+    /// Sends the request, waiting for the rate limit and retrying when that is safe.
+    ///
+    /// `429` responses are always retried after `Retry-After`. Connection errors are always
+    /// retried, other network errors and 5xx responses only for requests other than `POST`,
+    /// so a create is never sent twice.
+    pub async fn send(&self, req: RequestBuilder) -> Result<Response, SyncError> {
+        let mut attempt = 0;
+
+        loop {
+            let attempt_req = req
+                .try_clone()
+                .expect("Request bodies are always buffered JSON");
+            let (client, attempt_req) = attempt_req.build_split();
+            let attempt_req = attempt_req?;
+            let retryable = attempt_req.method() != Method::POST;
+
+            self.acquire().await;
+
+            let res = client.execute(attempt_req).await;
+
+            let wait = match &res {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    retry_after(res).unwrap_or_else(|| backoff(attempt))
+                }
+                Ok(res) if res.status().is_server_error() && retryable => backoff(attempt),
+                Err(e) if e.is_connect() || (retryable && (e.is_timeout() || e.is_request())) => {
+                    backoff(attempt)
+                }
+                _ => return Ok(res?),
+            };
+
+            // Out of retries, hand the last result to the caller
+            if attempt >= MAX_RETRIES {
+                return Ok(res?);
+            }
+
+            attempt += 1;
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Waits until another request fits in the rate limit window and claims it.
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+
+                while sent
+                    .front()
+                    .is_some_and(|x| now.duration_since(*x) >= self.limit.window)
+                {
+                    sent.pop_front();
+                }
+
+                match sent.front() {
+                    Some(oldest) if sent.len() >= self.limit.max_requests => {
+                        self.limit.window - now.duration_since(*oldest)
+                    }
+                    _ => {
+                        sent.push_back(now);
+                        return;
+                    }
+                }
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Reads the `Retry-After` header, in seconds.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let seconds = res
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse::<u64>()
+        .ok()?;
+
+    Some(Duration::from_secs(seconds))
+}
+
+/// Exponential backoff with full jitter.
+fn backoff(attempt: u32) -> Duration {
+    let max = BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_BACKOFF);
+
+    max.mul_f64(fastrand::f64())
+}
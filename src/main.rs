@@ -1,458 +1,295 @@
-use harvest::{CreateClient, HarvestClient};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Instant;
+
+use config::Config;
+use error::SyncError;
+use harvest::HarvestClient;
+use mapping::MappingStore;
 use rentman::RentmanClient;
-
+use schedule::{Schedule, Shutdown};
+use sync::Syncer;
+use webhook::Server;
+
+mod config;
+mod crew;
+mod error;
+mod executor;
+#[cfg(test)]
+mod fixtures;
 mod harvest;
+mod http;
+mod mapping;
+mod plan;
 mod rentman;
+mod rules;
+mod schedule;
+mod sync;
+mod time_entries;
+mod webhook;
+
+/// What the process does once it is set up.
+enum Mode {
+    /// A single sync, of everything when `full` is set.
+    Once { full: bool },
+    /// Keep running, syncing on a schedule, on Rentman webhooks, or both.
+    Serve {
+        schedule: Option<Schedule>,
+        webhook: Option<Server>,
+    },
+}
 
-async fn update_clients(harvest: &HarvestClient, rentman: &RentmanClient) {
-    // Get Harvest clients
-    let clients = match harvest.get_clients().await {
-        Ok(x) => x,
-        Err(e) => {
-            panic!("Error getting clients: {}", e);
-        }
-    };
+/// Syncs on the schedule and on webhooks until a shutdown is requested. Fails when the webhook
+/// server stopped with an error.
+async fn serve(
+    syncer: Arc<Syncer>,
+    schedule: Option<Schedule>,
+    webhook: Option<Server>,
+) -> ExitCode {
+    let shutdown = Shutdown::listen();
 
-    // Get Rentman contacts
-    let contacts = match rentman.get_contacts().await {
-        Ok(x) => x,
-        Err(e) => {
-            panic!("Error getting contacts: {}", e);
-        }
-    };
+    // Webhooks are skipped until a full sync adopted the legacy records, so don't wait for the
+    // schedule. Webhooks that arrive meanwhile wait on the bound listener.
+    let starts_now = schedule.as_ref().is_some_and(|x| x.first_delay().is_zero());
 
-    // Vec to store missing clients
-    let mut missing_clients: Vec<MissingClient> = vec![];
-
-    // Loop thru Rentman contacts
-    for contact in contacts.data {
-        // Loop thru Harvest clients to check if client is found
-        let found_client = clients.clients.iter().find(|x| {
-            let harvest_rentman_id = match x.address.as_ref() {
-                Some(x) => x,
-                None => {
-                    println!("heeft geen address");
-                    return false;
-                }
-            }
-            .parse::<i64>()
-            .unwrap_or(0);
-
-            harvest_rentman_id == contact.id
-        });
-
-        // Client is found, check for updates, then continue to next contact
-        if let Some(client) = found_client {
-            if client.name != contact.name {
-                // update contact name
-                println!(
-                    "Updating contact name \"{}\" to \"{}\"",
-                    client.name, contact.name
-                );
-
-                harvest
-                    .update_client(
-                        client.id,
-                        harvest::UpdateClient {
-                            name: Some(contact.name),
-                            address: None,
-                        },
-                    )
-                    .await
-                    .unwrap();
-            }
+    if webhook.is_some() && !starts_now && syncer.is_new().await {
+        println!("New mapping file, starting a full sync before accepting webhooks...");
 
-            continue;
-        }
-
-        // Push missing client to vec
-        missing_clients.push(MissingClient {
-            address: contact.id.to_string(),
-            name: contact.name,
-        })
+        run_logged(&syncer, true, &shutdown).await;
     }
 
-    // Insert missing clients
-    for client in missing_clients {
-        println!("Creating client: {:?}", client);
-
-        harvest
-            .create_client(CreateClient {
-                name: client.name,
-                address: client.address,
-            })
-            .await
-            .unwrap();
+    let server = webhook.map(|server| {
+        tokio::spawn(webhook::serve(
+            server,
+            Arc::clone(&syncer),
+            shutdown.clone(),
+        ))
+    });
+
+    if let Some(schedule) = schedule {
+        run_scheduled(&syncer, &schedule, shutdown.clone()).await;
     }
-}
 
-async fn update_projects(harvest: &HarvestClient, rentman: &RentmanClient) {
-    // Get Harvest projects
-    let harvest_projects = match harvest.get_projects().await {
-        Ok(x) => x,
-        Err(e) => {
-            panic!("Error getting Harvest Projects: {}", e);
-        }
+    let code = match server {
+        None => ExitCode::SUCCESS,
+        Some(server) => match server.await {
+            Ok(Ok(())) => ExitCode::SUCCESS,
+            Ok(Err(e)) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+            Err(e) => {
+                eprintln!("Webhook server stopped: {}", e);
+                ExitCode::FAILURE
+            }
+        },
     };
 
-    // Get Rentman projects
-    let rentman_projects = rentman
-        .get_projects()
-        .await
-        .expect("Can't fetch Rentman projects");
-
-    // Get Rentman subprojects
-    let rentman_subprojects = rentman
-        .get_subprojects()
-        .await
-        .expect("Can't fetch Rentman subprojects");
+    println!("Stopped");
 
-    // Get Harvest clients
-    let clients = match harvest.get_clients().await {
-        Ok(x) => x,
-        Err(e) => {
-            panic!("Error getting clients: {}", e);
-        }
-    };
+    code
+}
 
-    // Vec to store missing projects in Harvest
-    let mut missing_projects: Vec<MissingProject> = vec![];
+/// Runs a full sync on every tick of the schedule until a shutdown is requested.
+/// Runs never overlap, and a failed sync is logged and retried on the next tick.
+async fn run_scheduled(syncer: &Syncer, schedule: &Schedule, mut shutdown: Shutdown) {
+    let mut delay = schedule.first_delay();
 
-    for mut rentman_project in rentman_projects.data {
-        // If project is template, skip
-        if rentman_project.name.to_lowercase().contains("template") {
-            continue;
-        }
+    println!("Syncing {}", schedule);
 
-        // If project is BTDB, skip
-        if rentman_project.customer_id == rentman.btdb_id {
-            continue;
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.requested() => break,
         }
 
-        // Match subproject to project
-        let mut statuses: Vec<&rentman::Status> = vec![];
+        let started = Instant::now();
 
-        let mut is_template = false;
-        for subproject in &rentman_subprojects {
-            if subproject.project_id != rentman_project.id {
-                continue;
-            }
+        run_logged(syncer, false, &shutdown).await;
 
-            statuses.push(&subproject.status);
-
-            is_template = subproject.is_template;
+        if shutdown.is_requested() {
+            break;
         }
 
-        // Check state
+        delay = schedule.next_delay(started);
+    }
+}
 
-        let mut is_active = true;
-        if !statuses.is_empty() {
-            let first = statuses.first().unwrap();
+/// Runs a sync, logging how it went instead of failing.
+async fn run_logged(syncer: &Syncer, full: bool, shutdown: &Shutdown) {
+    let started = Instant::now();
 
-            if statuses.iter().all(|x| x == first) {
-                match first {
-                    rentman::Status::Geannuleerd => is_active = false,
-                    rentman::Status::Retour => is_active = false,
-                    rentman::Status::Factureren => is_active = false,
-                    _ => {}
-                }
-            }
-        }
+    println!("Starting sync...");
 
-        // if project contains template, skip
-        if is_template {
-            continue;
-        }
+    match syncer.run(full, shutdown).await {
+        Ok(0) => println!("Sync finished in {} seconds", started.elapsed().as_secs()),
+        Ok(failed) => eprintln!("Sync finished with {} failed changes", failed),
+        Err(e) => eprintln!("Sync failed: {}", e),
+    }
+}
 
-        // Loop thru Harvest projects to check if project is found
-        let found_project = harvest_projects.projects.iter().find(|x| {
-            let harvest_rentman_id = match x.notes.as_ref() {
-                Some(x) => x,
-                None => {
-                    println!("heeft geen notes");
-                    return false;
-                }
-            }
-            .parse::<i64>()
-            .unwrap_or(0);
-
-            harvest_rentman_id == rentman_project.id
-        });
-
-        // Project is found. Check for updates, then continue.
-        if let Some(harvest_project) = found_project {
-            rentman_project.name = rentman_project.name.trim().to_string();
-
-            if rentman_project.name != harvest_project.name {
-                // Update name
-
-                println!(
-                    "Updating project: \"{}\" to \"{}\"",
-                    harvest_project.name, rentman_project.name
-                );
-
-                harvest
-                    .update_project(
-                        harvest_project.id,
-                        harvest::UpdateProject {
-                            client_id: None,
-                            name: Some(rentman_project.name),
-                            notes: None,
-                            code: None,
-                            is_active: None,
-                        },
-                    )
-                    .await
-                    .unwrap();
-            }
+/// Command line arguments.
+struct Args {
+    config: Option<PathBuf>,
+    dry_run: bool,
+    /// Start a new mapping file when it doesn't exist.
+    init_mappings: bool,
+    /// Sync everything instead of only the changes since the last sync.
+    full: bool,
+    /// Keep running instead of syncing once.
+    serve: bool,
+    interval: Option<String>,
+    cron: Option<String>,
+}
 
-            match &harvest_project.code {
-                Some(code) => {
-                    if rentman_project.number.to_string() != *code {
-                        // Update code
-
-                        println!(
-                            "Updating project: \"{}\" to \"{}\"",
-                            harvest_project.code.as_ref().unwrap(),
-                            rentman_project.number
-                        );
-
-                        harvest
-                            .update_project(
-                                harvest_project.id,
-                                harvest::UpdateProject {
-                                    client_id: None,
-                                    name: None,
-                                    notes: None,
-                                    code: Some(rentman_project.number.to_string()),
-                                    is_active: None,
-                                },
-                            )
-                            .await
-                            .unwrap();
-                    }
-                }
-                None => {}
-            }
+impl Args {
+    fn parse() -> Result<Self, SyncError> {
+        let mut args = Args {
+            config: None,
+            dry_run: false,
+            init_mappings: false,
+            full: false,
+            serve: false,
+            interval: None,
+            cron: None,
+        };
 
-            // Define Rentman contact ID from Harvest
-            let harvest_client = match clients.clients.iter().find(|x| {
-                if x.id == harvest_project.client.id {
-                    return true;
-                };
-
-                false
-            }) {
-                Some(x) => x,
-                None => {
-                    println!("Client not found for project");
-                    continue;
-                }
-            };
+        let mut iter = std::env::args().skip(1);
 
-            let harvest_client_id = match harvest_client.address.as_ref() {
-                Some(x) => x.parse::<i64>().unwrap_or(0),
-                None => {
-                    println!("Client has no address");
-                    continue;
-                }
+        while let Some(arg) = iter.next() {
+            let (flag, inline) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg.as_str(), None),
             };
 
-            if rentman_project.customer_id != harvest_client_id {
-                // Update client
-
-                let client_id: i64 = if rentman_project.customer_id == 0 {
-                    harvest.nvt_client
-                } else {
-                    // Find correct Harvest client
-                    let client = clients.clients.iter().find(|x| {
-                        if x.address
-                            .as_ref()
-                            .is_some_and(|v| v == &rentman_project.customer_id.to_string())
-                        {
-                            return true;
-                        };
-
-                        false
-                    });
-
-                    if client.is_none() {
-                        println!("Client not found for project");
-                        continue;
-                    }
-
-                    client.unwrap().id
-                };
-
-                println!("Updating project: {} client", harvest_project.name,);
-
-                harvest
-                    .update_project(
-                        harvest_project.id,
-                        harvest::UpdateProject {
-                            client_id: Some(client_id),
-                            name: None,
-                            notes: None,
-                            code: None,
-                            is_active: None,
-                        },
-                    )
-                    .await
-                    .unwrap();
-            }
-
-            if is_active != harvest_project.is_active && harvest_project.is_active {
-                println!("Changing archival status for {}", harvest_project.name);
-
-                harvest
-                    .update_project(
-                        harvest_project.id,
-                        harvest::UpdateProject {
-                            client_id: None,
-                            name: None,
-                            notes: None,
-                            code: None,
-                            is_active: Some(is_active),
-                        },
-                    )
-                    .await
-                    .unwrap();
-            }
+            // Value of a flag, either as `--flag=value` or `--flag value`
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| iter.next())
+                    .ok_or_else(|| SyncError::Config(format!("{} needs a value", flag)))
+            };
 
-            if is_active != harvest_project.is_active {
-                let abc = rentman_project.planperiod_start.unwrap_or("0".to_string());
-
-                if abc.contains("2024")
-                    || harvest_project.name.contains("2024")
-                    || harvest_project.name.contains("-24")
-                    || harvest_project.name.contains("/24")
-                    || harvest_project.name.contains("'24")
-                {
-                    println!(
-                        "Archival status is not correct in Harvest for {} ({} - {})",
-                        harvest_project.name, is_active, abc
-                    );
-
-                    harvest
-                        .update_project(
-                            harvest_project.id,
-                            harvest::UpdateProject {
-                                client_id: None,
-                                name: None,
-                                notes: None,
-                                code: None,
-                                is_active: Some(is_active),
-                            },
-                        )
-                        .await
-                        .unwrap();
-                }
+            match flag {
+                "serve" => args.serve = true,
+                "--dry-run" => args.dry_run = true,
+                "--full" => args.full = true,
+                "--init-mappings" => args.init_mappings = true,
+                "--config" => args.config = Some(PathBuf::from(value()?)),
+                "--interval" => args.interval = Some(value()?),
+                "--cron" => args.cron = Some(value()?),
+                _ => return Err(SyncError::Config(format!("Unknown argument: {}", arg))),
             }
-
-            continue;
         }
 
-        // Push missing project to vec
-        missing_projects.push(MissingProject {
-            is_active,
-            rentman_id: rentman_project.id.to_string(),
-            name: rentman_project.name.clone(),
-            rentman_client_id: rentman_project.customer_id,
-            pp_id: rentman_project.number.to_string(),
-        })
+        Ok(args)
     }
 
-    for project in missing_projects {
-        let client_id: i64 = if project.rentman_client_id == 0 {
-            harvest.nvt_client
-        } else {
-            // Find correct Harvest client
-            let client = clients.clients.iter().find(|x| {
-                if x.address
-                    .as_ref()
-                    .is_some_and(|v| v == &project.rentman_client_id.to_string())
-                {
-                    return true;
-                };
-
-                false
-            });
-
-            if client.is_none() {
-                println!("Client not found for project: {}", project.name);
-                continue;
-            }
+    /// Whether to keep running instead of syncing once. `--interval` and `--cron` imply `serve`.
+    fn serving(&self) -> bool {
+        self.serve || self.interval.is_some() || self.cron.is_some()
+    }
 
-            client.unwrap().id
+    /// The schedule passed with `--interval` or `--cron`, which replaces the configured one.
+    fn schedule(&self) -> Result<Option<Schedule>, SyncError> {
+        let schedule = match (&self.interval, &self.cron) {
+            (Some(_), Some(_)) => {
+                return Err(SyncError::Config(
+                    "--interval and --cron can't both be set".to_string(),
+                ))
+            }
+            (Some(interval), None) => Schedule::parse_interval(interval)
+                .map_err(|e| SyncError::Config(format!("--interval: {}", e)))?,
+            (None, Some(cron)) => Schedule::parse_cron(cron)
+                .map_err(|e| SyncError::Config(format!("--cron: {}", e)))?,
+            (None, None) => return Ok(None),
         };
 
-        println!("Creating project: {:?} ({})", project, client_id);
-
-        harvest
-            .create_project(harvest::CreateProject {
-                is_active: project.is_active,
-                name: project.name,
-                client_id,
-                code: project.pp_id,
-                notes: project.rentman_id.to_string(),
-                bill_by: "none".to_string(),
-                budget_by: "none".to_string(),
-                is_billable: true,
-            })
-            .await
-            .unwrap();
+        Ok(Some(schedule))
     }
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     dotenv::dotenv().ok();
 
-    let harvest_token = std::env::var("HARVEST_TOKEN").expect("No Harvest token defined");
-    let harvest_account_id =
-        std::env::var("HARVEST_ACCOUNT_ID").expect("No Harvest Account ID defined");
-    let harvest_user_agent =
-        std::env::var("HARVEST_USER_AGENT").expect("No Harvest User Agent defined");
-    let nvt_client = std::env::var("HARVEST_NVT_CLIENT")
-        .expect("No Harvest NVT Client defined")
-        .parse::<i64>()
-        .expect("Harvest NVT client is not a valid number");
-
-    let rentman_token = std::env::var("RENTMAN_TOKEN").expect("No Rentman token defined");
-    let btdb_id = std::env::var("RENTMAN_BTDB_ID")
-        .expect("No Rentman BTDB ID defined")
-        .parse::<i64>()
-        .expect("Rentman BTDB ID is not a valid number");
+    let (syncer, mode) = match setup() {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
 
-    let harvest = HarvestClient::new(
-        harvest_token,
-        harvest_account_id,
-        harvest_user_agent,
-        nvt_client,
-    );
+    if syncer.dry_run {
+        println!("Dry run, nothing will be written to Harvest or Rentman");
+    }
 
-    let rentman = RentmanClient::new(rentman_token, btdb_id);
+    let full = match mode {
+        Mode::Serve { schedule, webhook } => {
+            return serve(Arc::new(syncer), schedule, webhook).await;
+        }
+        Mode::Once { full } => full,
+    };
 
     println!("Starting sync...");
 
-    // Clients
-    update_clients(&harvest, &rentman).await;
-
-    // Projects
-    update_projects(&harvest, &rentman).await;
+    match syncer.run(full, &Shutdown::listen()).await {
+        Ok(0) => ExitCode::SUCCESS,
+        Ok(failed) => {
+            eprintln!("Sync finished with {} failed changes", failed);
+            ExitCode::FAILURE
+        }
+        Err(e) => {
+            eprintln!("Sync failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-#[derive(Debug)]
-struct MissingClient {
-    name: String,
-    address: String,
-}
+fn setup() -> Result<(Syncer, Mode), SyncError> {
+    let args = Args::parse()?;
+    let config = Config::load(args.config.as_deref())?;
+
+    let mode = if args.serving() {
+        let schedule = args.schedule()?.or(config.schedule);
+
+        if schedule.is_none() && config.webhook.is_none() {
+            return Err(SyncError::Config(
+                "serve needs a schedule or a webhook, set schedule.interval, schedule.cron or webhook.listen, or pass --interval or --cron"
+                    .to_string(),
+            ));
+        }
+
+        Mode::Serve {
+            schedule,
+            webhook: config.webhook.map(Server::bind).transpose()?,
+        }
+    } else {
+        Mode::Once { full: args.full }
+    };
+
+    let harvest = HarvestClient::new(
+        &config.harvest.token,
+        &config.harvest.account_id,
+        &config.harvest.user_agent,
+    )?
+    .with_base_url(&config.harvest.base_url);
+
+    let rentman =
+        RentmanClient::new(&config.rentman.token)?.with_base_url(&config.rentman.base_url);
+
+    let mappings = MappingStore::load(&config.mapping_file, args.init_mappings)?;
+
+    let syncer = Syncer::new(
+        harvest,
+        rentman,
+        mappings,
+        config.settings,
+        args.dry_run,
+        config.full_sync_every,
+    );
 
-#[derive(Debug)]
-struct MissingProject {
-    name: String,
-    rentman_id: String,
-    rentman_client_id: i64,
-    pp_id: String,
-    is_active: bool,
+    Ok((syncer, mode))
 }
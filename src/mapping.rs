@@ -0,0 +1,229 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SyncError;
+use crate::harvest;
+
+/// Links Rentman records to the Harvest records they are synced to, persisted as a JSON file
+/// together with the times of the last syncs.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct MappingStore {
+    #[serde(skip)]
+    path: PathBuf,
+    /// The file didn't exist yet, so legacy Harvest records still have to be adopted.
+    #[serde(skip)]
+    pub created: bool,
+    /// Harvest client ID by Rentman contact ID.
+    #[serde(default)]
+    pub clients: BTreeMap<i64, i64>,
+    /// Harvest project ID by Rentman project ID.
+    #[serde(default)]
+    pub projects: BTreeMap<i64, i64>,
+    /// Harvest task assignments created for Rentman subprojects. Only these are deactivated
+    /// when their subproject goes away.
+    #[serde(default)]
+    pub task_assignments: BTreeSet<i64>,
+    /// Harvest user assignments created for planned Rentman crew. Only these are deactivated
+    /// when the crew member is no longer planned.
+    #[serde(default)]
+    pub user_assignments: BTreeSet<i64>,
+    /// `updated_at` of Harvest clients after this tool last wrote them, by Harvest client ID, to
+    /// tell its own changes from edits by hand.
+    #[serde(default)]
+    pub written_clients: BTreeMap<i64, String>,
+    /// `updated_at` of Harvest projects after this tool last wrote them, by Harvest project ID.
+    #[serde(default)]
+    pub written_projects: BTreeMap<i64, String>,
+    /// Rentman time registrations by Harvest time entry ID.
+    #[serde(default)]
+    pub time_entries: BTreeMap<i64, SyncedTimeEntry>,
+    /// Rentman contacts whose changes failed to apply, synced again by the next sync.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub retry_contacts: BTreeSet<i64>,
+    /// Rentman projects whose changes failed to apply.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub retry_projects: BTreeSet<i64>,
+    /// Start of the last sync that ran to the end, full or incremental.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_at: Option<DateTime<Utc>>,
+    /// Start of the last full sync that ran to the end.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub full_synced_at: Option<DateTime<Utc>>,
+}
+
+/// A Harvest time entry that was written to Rentman.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncedTimeEntry {
+    /// `None` while the registration is being created. If a sync stops before it is filled in,
+    /// the registration may or may not exist in Rentman.
+    pub registration_id: Option<i64>,
+    /// `updated_at` of the time entry when it was written, to detect later edits.
+    pub updated_at: String,
+    /// Day the time was spent, to tell deleted entries from ones outside the synced days.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spent_date: Option<NaiveDate>,
+}
+
+impl MappingStore {
+    /// Loads the store from `path`. A missing file is an error unless `init` is set, as an
+    /// empty store would create every Rentman record in Harvest again.
+    pub fn load(path: &Path, init: bool) -> Result<Self, SyncError> {
+        let mut store = match std::fs::read_to_string(path) {
+            Ok(x) => serde_json::from_str::<MappingStore>(&x)
+                .map_err(|e| SyncError::Store(format!("{} is invalid: {}", path.display(), e)))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && init => MappingStore {
+                created: true,
+                ..Default::default()
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(SyncError::Store(format!(
+                    "{} does not exist. Pass --init-mappings to start a new one, or set MAPPING_FILE to the existing one",
+                    path.display()
+                )))
+            }
+            Err(e) => {
+                return Err(SyncError::Store(format!(
+                    "Can't read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+
+        store.path = path.to_path_buf();
+
+        Ok(store)
+    }
+
+    /// Writes the store to a temporary file and moves it over the old one.
+    pub fn save(&self) -> Result<(), SyncError> {
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| SyncError::Store(format!("Can't serialize mappings: {}", e)))?;
+
+        let tmp = self.path.with_extension("json.tmp");
+
+        std::fs::write(&tmp, json)
+            .and_then(|_| std::fs::rename(&tmp, &self.path))
+            .map_err(|e| SyncError::Store(format!("Can't write {}: {}", self.path.display(), e)))
+    }
+
+    /// Adopts unmapped Harvest records that carry a Rentman ID in the legacy `address` (clients)
+    /// or `notes` (projects) field. Returns the number of new mappings. Only run on a new store,
+    /// after that these fields are free to use.
+    pub fn bootstrap(
+        &mut self,
+        clients: &[harvest::ClientData],
+        projects: &[harvest::Project],
+    ) -> usize {
+        let clients = clients
+            .iter()
+            .filter_map(|x| Some((x.address.as_ref()?.trim().parse::<i64>().ok()?, x.id)));
+
+        let projects = projects
+            .iter()
+            .filter_map(|x| Some((x.notes.as_ref()?.trim().parse::<i64>().ok()?, x.id)));
+
+        adopt(&mut self.clients, clients) + adopt(&mut self.projects, projects)
+    }
+
+    /// Whether the client wasn't changed since this tool last wrote it.
+    pub fn wrote_client(&self, client: &harvest::ClientData) -> bool {
+        self.written_clients.get(&client.id) == Some(&client.updated_at)
+    }
+
+    /// Whether the project wasn't changed since this tool last wrote it.
+    pub fn wrote_project(&self, project: &harvest::Project) -> bool {
+        project
+            .updated_at
+            .as_ref()
+            .is_some_and(|x| self.written_projects.get(&project.id) == Some(x))
+    }
+
+    /// Rentman project ID by Harvest project ID.
+    pub fn rentman_projects_by_project(&self) -> HashMap<i64, i64> {
+        self.projects.iter().map(|(k, v)| (*v, *k)).collect()
+    }
+
+    /// Rentman contact ID by Harvest client ID.
+    pub fn contacts_by_client(&self) -> HashMap<i64, i64> {
+        self.clients.iter().map(|(k, v)| (*v, *k)).collect()
+    }
+}
+
+/// Inserts `(rentman_id, harvest_id)` pairs where neither side is mapped yet.
+fn adopt(map: &mut BTreeMap<i64, i64>, pairs: impl Iterator<Item = (i64, i64)>) -> usize {
+    let mut added = 0;
+
+    for (rentman_id, harvest_id) in pairs {
+        if map.contains_key(&rentman_id) || map.values().any(|x| *x == harvest_id) {
+            continue;
+        }
+
+        map.insert(rentman_id, harvest_id);
+        added += 1;
+    }
+
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{client, harvest_project};
+
+    fn legacy_client(id: i64, address: Option<&str>) -> harvest::ClientData {
+        let mut client = client(id, &format!("Client {}", id));
+        client.address = address.map(str::to_string);
+        client
+    }
+
+    fn legacy_project(id: i64, notes: Option<&str>) -> harvest::Project {
+        let mut project = harvest_project(id, &format!("Project {}", id), "", 1);
+        project.notes = notes.map(str::to_string);
+        project
+    }
+
+    #[test]
+    fn bootstrap_adopts_legacy_ids() {
+        let mut store = MappingStore::default();
+
+        let added = store.bootstrap(
+            &[
+                legacy_client(1, Some(" 501 ")),
+                legacy_client(2, Some("Dorpsstraat 1")),
+                legacy_client(3, None),
+            ],
+            &[
+                legacy_project(10, Some("2001")),
+                legacy_project(11, Some("")),
+            ],
+        );
+
+        assert_eq!(added, 2);
+        assert_eq!(store.clients, BTreeMap::from([(501, 1)]));
+        assert_eq!(store.projects, BTreeMap::from([(2001, 10)]));
+    }
+
+    #[test]
+    fn bootstrap_keeps_existing_mappings() {
+        let mut store = MappingStore {
+            clients: BTreeMap::from([(501, 1)]),
+            ..Default::default()
+        };
+
+        let added = store.bootstrap(
+            &[
+                legacy_client(2, Some("501")),
+                legacy_client(1, Some("502")),
+                legacy_client(4, Some("503")),
+            ],
+            &[],
+        );
+
+        assert_eq!(added, 1);
+        assert_eq!(store.clients, BTreeMap::from([(501, 1), (503, 4)]));
+    }
+}
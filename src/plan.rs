@@ -0,0 +1,1692 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::crew;
+use crate::mapping::MappingStore;
+use crate::rules::{
+    ArchiveRules, Budget, BudgetRules, BudgetSource, OrphanAction, OrphanRules, ProjectRules,
+};
+use crate::time_entries::TimeEntrySettings;
+use crate::{harvest, rentman};
+
+/// Settings that influence how Rentman data is mapped onto Harvest.
+pub struct Settings {
+    /// Harvest client used for Rentman projects without a customer.
+    pub nvt_client: i64,
+    pub projects: ProjectRules,
+    pub project_defaults: ProjectDefaults,
+    pub archive: ArchiveRules,
+    pub orphans: OrphanRules,
+    /// Set Harvest project budgets from Rentman, if set.
+    pub budget: Option<BudgetRules>,
+    /// Assign a Harvest task to each project for every Rentman subproject.
+    pub sync_tasks: bool,
+    /// Assign the Harvest users of the crew planned on each Rentman project.
+    pub sync_users: bool,
+    /// Rentman crew member ID by Harvest user ID, for users whose email differs between the two.
+    pub crew: HashMap<i64, i64>,
+    /// Write Harvest time entries to Rentman, if set.
+    pub time_entries: Option<TimeEntrySettings>,
+}
+
+/// Harvest fields of created projects that don't come from Rentman.
+#[derive(Debug, Clone)]
+pub struct ProjectDefaults {
+    pub bill_by: String,
+    pub budget_by: String,
+    pub is_billable: bool,
+}
+
+/// Everything fetched from Harvest and Rentman that a plan is computed from.
+///
+/// A targeted sync only fetches the affected records; anything missing from Harvest is created.
+pub struct Input {
+    /// Everything was fetched, so mapped records missing from Rentman were deleted there.
+    pub complete: bool,
+    pub clients: Vec<harvest::ClientData>,
+    pub harvest_projects: Vec<harvest::Project>,
+    pub contacts: Vec<rentman::ContactsData>,
+    pub rentman_projects: Vec<rentman::ProjectsData>,
+    pub subprojects: Vec<rentman::SubprojectData>,
+    pub statuses: rentman::StatusTable,
+    /// Functions of the projects in `rentman_projects`, only fetched when budgeting by hours.
+    pub functions: Vec<rentman::ProjectFunctionData>,
+    /// Only fetched when `Settings::sync_tasks` is set.
+    pub tasks: Vec<harvest::Task>,
+    /// Assignments of the projects in `harvest_projects`, only fetched when `Settings::sync_tasks` is set.
+    pub task_assignments: Vec<harvest::TaskAssignment>,
+    /// The rest is only fetched when `Settings::sync_users` is set.
+    pub users: Vec<harvest::User>,
+    pub crew: Vec<rentman::CrewData>,
+    /// Crew planned on the projects in `rentman_projects`.
+    pub project_crew: Vec<rentman::ProjectCrewData>,
+    /// Assignments of the projects in `harvest_projects`.
+    pub user_assignments: Vec<harvest::UserAssignment>,
+}
+
+/// Everything the sync intends to change in Harvest, in the order it should be applied.
+#[derive(Debug, Default)]
+pub struct SyncPlan {
+    pub actions: Vec<Action>,
+    /// Records that could not be planned, e.g. because their client is missing.
+    pub warnings: Vec<String>,
+    /// Projects left out by the project rules, with the reason.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum Action {
+    CreateClient {
+        rentman_id: i64,
+        name: String,
+    },
+    UpdateClient {
+        id: i64,
+        name: String,
+        changes: Vec<ClientChange>,
+    },
+    CreateProject {
+        rentman_id: i64,
+        name: String,
+        code: String,
+        client: ClientRef,
+        is_active: bool,
+        defaults: ProjectDefaults,
+        budget: Option<Budget>,
+        starts_on: Option<NaiveDate>,
+        ends_on: Option<NaiveDate>,
+    },
+    UpdateProject {
+        id: i64,
+        name: String,
+        changes: Vec<ProjectChange>,
+    },
+    CreateTask {
+        name: String,
+    },
+    /// Unarchives a task, so it can be assigned again.
+    ReactivateTask {
+        id: i64,
+        name: String,
+    },
+    AssignTask {
+        project: ProjectRef,
+        project_name: String,
+        task: TaskRef,
+        task_name: String,
+    },
+    SetTaskActive {
+        project_id: i64,
+        project_name: String,
+        id: i64,
+        task_name: String,
+        is_active: bool,
+    },
+    AssignUser {
+        project: ProjectRef,
+        project_name: String,
+        user_id: i64,
+        user_name: String,
+    },
+    /// Harvest keeps user assignments with time entries, so they are deactivated instead of
+    /// removed.
+    SetUserActive {
+        project_id: i64,
+        project_name: String,
+        id: i64,
+        user_name: String,
+        is_active: bool,
+    },
+}
+
+#[derive(Debug)]
+pub enum ClientChange {
+    Rename { to: String },
+    Archive { reason: String },
+}
+
+#[derive(Debug)]
+pub enum ProjectChange {
+    Rename {
+        to: String,
+    },
+    Recode {
+        from: String,
+        to: String,
+    },
+    Reassign {
+        from: i64,
+        to: ClientRef,
+    },
+    SetActive {
+        is_active: bool,
+        reason: String,
+    },
+    SetBudget {
+        to: Budget,
+    },
+    /// Only the dates Rentman has are set; the others are left as they are.
+    Reschedule {
+        starts_on: Option<NaiveDate>,
+        ends_on: Option<NaiveDate>,
+    },
+}
+
+/// A Harvest client, which may only be created earlier in the same plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientRef {
+    Existing(i64),
+    /// Created by a `CreateClient` action for this Rentman contact ID.
+    New(i64),
+}
+
+/// A Harvest project, which may only be created earlier in the same plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProjectRef {
+    Existing(i64),
+    /// Created by a `CreateProject` action for this Rentman project ID.
+    New(i64),
+}
+
+/// A Harvest task, which may only be created earlier in the same plan.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TaskRef {
+    Existing(i64),
+    /// Created by a `CreateTask` action with this name.
+    New(String),
+}
+
+/// Harvest tasks and task assignments, for planning the tasks of subprojects.
+struct TaskIndex<'a> {
+    /// Tasks by lowercase name.
+    by_name: HashMap<String, &'a harvest::Task>,
+    /// Task assignments by Harvest project ID.
+    assignments: HashMap<i64, Vec<&'a harvest::TaskAssignment>>,
+    /// Lowercase names of tasks created earlier in the plan.
+    planned: HashSet<String>,
+    /// Archived tasks reactivated earlier in the plan.
+    reactivated: HashSet<i64>,
+}
+
+/// Harvest users and user assignments, for planning the users of planned crew.
+struct UserIndex<'a> {
+    /// Active Harvest users by Rentman crew member ID.
+    by_crew: HashMap<i64, &'a harvest::User>,
+    /// User assignments by Harvest project ID.
+    assignments: HashMap<i64, Vec<&'a harvest::UserAssignment>>,
+    /// Planned crew member IDs by Rentman project ID.
+    planned: HashMap<i64, BTreeSet<i64>>,
+    /// Crew member names by ID.
+    names: HashMap<i64, &'a str>,
+    /// Names of planned crew members without a Harvest user.
+    unmapped: BTreeSet<String>,
+}
+
+/// Computes the changes needed to bring Harvest in line with Rentman. Does not touch the network.
+pub fn plan(
+    input: &Input,
+    mappings: &MappingStore,
+    settings: &Settings,
+    now: DateTime<Utc>,
+) -> SyncPlan {
+    let mut plan = SyncPlan::default();
+
+    for name in settings.projects.unknown_statuses(&input.statuses) {
+        plan.warnings.push(format!(
+            "Project rules refer to unknown Rentman status \"{}\"",
+            name
+        ));
+    }
+
+    // Harvest clients by Rentman contact ID
+    let clients_by_rentman_id: HashMap<i64, &harvest::ClientData> = mappings
+        .clients
+        .iter()
+        .filter_map(|(rentman_id, harvest_id)| {
+            Some((
+                *rentman_id,
+                input.clients.iter().find(|x| x.id == *harvest_id)?,
+            ))
+        })
+        .collect();
+
+    plan_clients(&mut plan, &clients_by_rentman_id, &input.contacts);
+
+    if input.complete && settings.orphans.archive_clients {
+        plan_orphan_clients(&mut plan, input, mappings, settings);
+    }
+
+    let mut tasks = TaskIndex {
+        by_name: input
+            .tasks
+            .iter()
+            .map(|x| (x.name.to_lowercase(), x))
+            .collect(),
+        assignments: HashMap::new(),
+        planned: HashSet::new(),
+        reactivated: HashSet::new(),
+    };
+
+    for assignment in &input.task_assignments {
+        tasks
+            .assignments
+            .entry(assignment.project.id)
+            .or_default()
+            .push(assignment);
+    }
+
+    plan_projects(
+        &mut plan,
+        input,
+        &clients_by_rentman_id,
+        mappings,
+        settings,
+        &mut tasks,
+        now,
+    );
+
+    if input.complete {
+        plan_orphan_projects(&mut plan, input, mappings, settings);
+    }
+
+    plan
+}
+
+fn plan_clients(
+    plan: &mut SyncPlan,
+    clients_by_rentman_id: &HashMap<i64, &harvest::ClientData>,
+    contacts: &[rentman::ContactsData],
+) {
+    for contact in contacts {
+        match clients_by_rentman_id.get(&contact.id) {
+            // Client is found, check for updates
+            Some(client) => {
+                if client.name != contact.name {
+                    plan.actions.push(Action::UpdateClient {
+                        id: client.id,
+                        name: client.name.clone(),
+                        changes: vec![ClientChange::Rename {
+                            to: contact.name.clone(),
+                        }],
+                    });
+                }
+            }
+            None => plan.actions.push(Action::CreateClient {
+                rentman_id: contact.id,
+                name: contact.name.clone(),
+            }),
+        }
+    }
+}
+
+/// Archives the Harvest clients of deleted Rentman contacts, unless they still have active
+/// projects or are kept.
+fn plan_orphan_clients(
+    plan: &mut SyncPlan,
+    input: &Input,
+    mappings: &MappingStore,
+    settings: &Settings,
+) {
+    for (rentman_id, harvest_id) in &mappings.clients {
+        if input.contacts.iter().any(|x| x.id == *rentman_id)
+            || *harvest_id == settings.nvt_client
+            || settings.orphans.keep_clients.contains(harvest_id)
+        {
+            continue;
+        }
+
+        let Some(client) = input
+            .clients
+            .iter()
+            .find(|x| x.id == *harvest_id && x.is_active)
+        else {
+            continue;
+        };
+
+        let active_projects = input
+            .harvest_projects
+            .iter()
+            .filter(|x| x.client.id == client.id && x.is_active)
+            .count();
+
+        if active_projects > 0 {
+            plan.warnings.push(format!(
+                "Rentman contact of client {} was deleted, but it still has {} active projects",
+                client.name, active_projects
+            ));
+            continue;
+        }
+
+        plan.actions.push(Action::UpdateClient {
+            id: client.id,
+            name: client.name.clone(),
+            changes: vec![ClientChange::Archive {
+                reason: format!("Rentman contact {} was deleted", rentman_id),
+            }],
+        });
+    }
+}
+
+/// Reports, tags or archives the Harvest projects of deleted Rentman projects.
+fn plan_orphan_projects(
+    plan: &mut SyncPlan,
+    input: &Input,
+    mappings: &MappingStore,
+    settings: &Settings,
+) {
+    let orphans = &settings.orphans;
+
+    for (rentman_id, harvest_id) in &mappings.projects {
+        if input.rentman_projects.iter().any(|x| x.id == *rentman_id) {
+            continue;
+        }
+
+        let Some(project) = input.harvest_projects.iter().find(|x| x.id == *harvest_id) else {
+            continue;
+        };
+
+        // Archived projects were handled before
+        if !project.is_active {
+            continue;
+        }
+
+        let reason = format!("Rentman project {} was deleted", rentman_id);
+
+        match orphans.projects {
+            OrphanAction::Report => plan.warnings.push(format!(
+                "{}, but Harvest project {} is still active",
+                reason, project.name
+            )),
+            OrphanAction::Tag if !project.name.starts_with(&orphans.tag) => {
+                plan.actions.push(Action::UpdateProject {
+                    id: project.id,
+                    name: project.name.clone(),
+                    changes: vec![ProjectChange::Rename {
+                        to: format!("{} {}", orphans.tag, project.name),
+                    }],
+                })
+            }
+            OrphanAction::Archive => plan.actions.push(Action::UpdateProject {
+                id: project.id,
+                name: project.name.clone(),
+                changes: vec![ProjectChange::SetActive {
+                    is_active: false,
+                    reason,
+                }],
+            }),
+            _ => {}
+        }
+    }
+}
+
+fn plan_projects(
+    plan: &mut SyncPlan,
+    input: &Input,
+    clients_by_rentman_id: &HashMap<i64, &harvest::ClientData>,
+    mappings: &MappingStore,
+    settings: &Settings,
+    tasks: &mut TaskIndex,
+    now: DateTime<Utc>,
+) {
+    let statuses = settings.archive.statuses.for_account(&input.statuses);
+
+    for name in &statuses.unknown {
+        plan.warnings.push(format!(
+            "Status mapping refers to unknown Rentman status \"{}\"",
+            name
+        ));
+    }
+
+    // Harvest projects by Rentman project ID
+    let projects_by_rentman_id: HashMap<i64, &harvest::Project> = mappings
+        .projects
+        .iter()
+        .filter_map(|(rentman_id, harvest_id)| {
+            Some((
+                *rentman_id,
+                input
+                    .harvest_projects
+                    .iter()
+                    .find(|x| x.id == *harvest_id)?,
+            ))
+        })
+        .collect();
+
+    let contacts_by_client = mappings.contacts_by_client();
+
+    let mut users = UserIndex::new(input, settings);
+
+    // Resolves a Rentman customer to a Harvest client, including clients created by this plan
+    let resolve_client = |customer_id: i64| -> Option<ClientRef> {
+        if customer_id == 0 {
+            return Some(ClientRef::Existing(settings.nvt_client));
+        }
+
+        if let Some(client) = clients_by_rentman_id.get(&customer_id) {
+            return Some(ClientRef::Existing(client.id));
+        }
+
+        input
+            .contacts
+            .iter()
+            .any(|x| x.id == customer_id)
+            .then_some(ClientRef::New(customer_id))
+    };
+
+    for rentman_project in &input.rentman_projects {
+        // Match subprojects to project
+        let project_subprojects: Vec<&rentman::SubprojectData> = input
+            .subprojects
+            .iter()
+            .filter(|x| x.project_id == rentman_project.id)
+            .collect();
+
+        if let Some(reason) =
+            settings
+                .projects
+                .skip_reason(rentman_project, &project_subprojects, &input.statuses)
+        {
+            plan.skipped.push(format!(
+                "Skipping project {} ({}): {}",
+                rentman_project.name, rentman_project.number, reason
+            ));
+            continue;
+        }
+
+        let active_state =
+            settings
+                .archive
+                .active_state(rentman_project, &project_subprojects, &statuses, now);
+
+        let name = rentman_project.name.trim().to_string();
+        let code = rentman_project.number.to_string();
+        let (starts_on, ends_on) = rentman_project.plan_dates();
+
+        let budget = settings.budget.as_ref().map(|rules| {
+            let functions: Vec<&rentman::ProjectFunctionData> = input
+                .functions
+                .iter()
+                .filter(|x| x.project_id == rentman_project.id)
+                .collect();
+
+            rules.budget(rentman_project, &functions)
+        });
+
+        let Some(harvest_project) = projects_by_rentman_id.get(&rentman_project.id) else {
+            // Project is missing in Harvest
+            match resolve_client(rentman_project.customer_id) {
+                Some(client) => {
+                    plan.actions.push(Action::CreateProject {
+                        rentman_id: rentman_project.id,
+                        name: name.clone(),
+                        code,
+                        client,
+                        is_active: active_state.as_ref().is_none_or(|(x, _)| *x),
+                        defaults: settings.project_defaults.clone(),
+                        budget,
+                        starts_on,
+                        ends_on,
+                    });
+
+                    if settings.sync_tasks {
+                        plan_tasks(
+                            plan,
+                            tasks,
+                            mappings,
+                            ProjectRef::New(rentman_project.id),
+                            &name,
+                            &project_subprojects,
+                        );
+                    }
+
+                    if settings.sync_users {
+                        plan_users(
+                            plan,
+                            &mut users,
+                            mappings,
+                            ProjectRef::New(rentman_project.id),
+                            &name,
+                            rentman_project.id,
+                        );
+                    }
+                }
+                None => plan
+                    .warnings
+                    .push(format!("Client not found for project: {}", name)),
+            }
+
+            continue;
+        };
+
+        // Project is found, check for updates
+        if settings.sync_tasks {
+            plan_tasks(
+                plan,
+                tasks,
+                mappings,
+                ProjectRef::Existing(harvest_project.id),
+                &harvest_project.name,
+                &project_subprojects,
+            );
+        }
+
+        if settings.sync_users {
+            plan_users(
+                plan,
+                &mut users,
+                mappings,
+                ProjectRef::Existing(harvest_project.id),
+                &harvest_project.name,
+                rentman_project.id,
+            );
+        }
+
+        let mut changes: Vec<ProjectChange> = vec![];
+
+        if name != harvest_project.name {
+            changes.push(ProjectChange::Rename { to: name.clone() });
+        }
+
+        if let Some(from) = harvest_project.code.as_ref().filter(|x| **x != code) {
+            changes.push(ProjectChange::Recode {
+                from: from.clone(),
+                to: code,
+            });
+        }
+
+        if starts_on.is_some_and(|x| harvest_project.starts_on != Some(x))
+            || ends_on.is_some_and(|x| harvest_project.ends_on != Some(x))
+        {
+            changes.push(ProjectChange::Reschedule { starts_on, ends_on });
+        }
+
+        if let Some(budget) = budget.filter(|x| !x.matches(harvest_project)) {
+            changes.push(ProjectChange::SetBudget { to: budget });
+        }
+
+        // Define Rentman contact ID from Harvest
+        let current_customer_id = if harvest_project.client.id == settings.nvt_client {
+            Some(0)
+        } else {
+            contacts_by_client.get(&harvest_project.client.id).copied()
+        };
+
+        let Some(current_customer_id) = current_customer_id else {
+            plan.warnings.push(format!(
+                "Client of project {} is not mapped to a Rentman contact",
+                harvest_project.name
+            ));
+            push_project_update(plan, harvest_project, changes);
+            continue;
+        };
+
+        if rentman_project.customer_id != current_customer_id {
+            match resolve_client(rentman_project.customer_id) {
+                Some(to) => changes.push(ProjectChange::Reassign {
+                    from: harvest_project.client.id,
+                    to,
+                }),
+                None => {
+                    plan.warnings.push(format!(
+                        "Client not found for project: {}",
+                        harvest_project.name
+                    ));
+                    push_project_update(plan, harvest_project, changes);
+                    continue;
+                }
+            }
+        }
+
+        // Archive active projects, but only reactivate recent projects
+        if let Some((is_active, reason)) = active_state {
+            if is_active != harvest_project.is_active
+                && (harvest_project.is_active
+                    || settings.archive.may_reactivate(rentman_project, now))
+            {
+                changes.push(ProjectChange::SetActive { is_active, reason });
+            }
+        }
+
+        push_project_update(plan, harvest_project, changes);
+    }
+
+    for name in users.unmapped {
+        plan.warnings.push(format!(
+            "Rentman crew member {} has no Harvest user, add their ID to crew",
+            name
+        ));
+    }
+}
+
+/// Assigns a task named after each subproject to the project, and deactivates the task
+/// assignments created for subprojects that are gone or renamed.
+fn plan_tasks(
+    plan: &mut SyncPlan,
+    tasks: &mut TaskIndex,
+    mappings: &MappingStore,
+    project: ProjectRef,
+    project_name: &str,
+    subprojects: &[&rentman::SubprojectData],
+) {
+    let mut subprojects = subprojects.to_vec();
+    subprojects.sort_by_key(|x| x.order);
+
+    // Subprojects with the same name share a task
+    let mut wanted: Vec<&str> = vec![];
+
+    for subproject in subprojects {
+        let name = subproject.name.trim();
+
+        if !name.is_empty() && !wanted.iter().any(|x| x.eq_ignore_ascii_case(name)) {
+            wanted.push(name);
+        }
+    }
+
+    let assignments: &[&harvest::TaskAssignment] = match project {
+        ProjectRef::Existing(id) => tasks.assignments.get(&id).map_or(&[], |x| x.as_slice()),
+        ProjectRef::New(_) => &[],
+    };
+
+    for name in &wanted {
+        let assignment = assignments
+            .iter()
+            .find(|x| x.task.name.eq_ignore_ascii_case(name));
+
+        match (assignment, project) {
+            (Some(assignment), ProjectRef::Existing(project_id)) => {
+                if !assignment.is_active && mappings.task_assignments.contains(&assignment.id) {
+                    plan.actions.push(Action::SetTaskActive {
+                        project_id,
+                        project_name: project_name.to_string(),
+                        id: assignment.id,
+                        task_name: assignment.task.name.clone(),
+                        is_active: true,
+                    });
+                }
+            }
+            _ => {
+                let key = name.to_lowercase();
+
+                let task = match tasks.by_name.get(&key) {
+                    Some(task) => {
+                        // Harvest doesn't assign archived tasks
+                        if !task.is_active && tasks.reactivated.insert(task.id) {
+                            plan.actions.push(Action::ReactivateTask {
+                                id: task.id,
+                                name: task.name.clone(),
+                            });
+                        }
+
+                        TaskRef::Existing(task.id)
+                    }
+                    None => {
+                        if tasks.planned.insert(key) {
+                            plan.actions.push(Action::CreateTask {
+                                name: name.to_string(),
+                            });
+                        }
+
+                        TaskRef::New(name.to_string())
+                    }
+                };
+
+                plan.actions.push(Action::AssignTask {
+                    project,
+                    project_name: project_name.to_string(),
+                    task,
+                    task_name: name.to_string(),
+                });
+            }
+        }
+    }
+
+    let ProjectRef::Existing(project_id) = project else {
+        return;
+    };
+
+    for assignment in assignments {
+        if assignment.is_active
+            && mappings.task_assignments.contains(&assignment.id)
+            && !wanted
+                .iter()
+                .any(|x| x.eq_ignore_ascii_case(&assignment.task.name))
+        {
+            plan.actions.push(Action::SetTaskActive {
+                project_id,
+                project_name: project_name.to_string(),
+                id: assignment.id,
+                task_name: assignment.task.name.clone(),
+                is_active: false,
+            });
+        }
+    }
+}
+
+impl<'a> UserIndex<'a> {
+    fn new(input: &'a Input, settings: &Settings) -> Self {
+        let crew_by_user = crew::crew_by_user(&input.users, &input.crew, &settings.crew);
+
+        let mut by_crew: HashMap<i64, &harvest::User> = HashMap::new();
+
+        for user in input.users.iter().filter(|x| x.is_active) {
+            if let Some(crew_id) = crew_by_user.get(&user.id) {
+                by_crew.entry(*crew_id).or_insert(user);
+            }
+        }
+
+        let mut assignments: HashMap<i64, Vec<&harvest::UserAssignment>> = HashMap::new();
+
+        for assignment in &input.user_assignments {
+            assignments
+                .entry(assignment.project.id)
+                .or_default()
+                .push(assignment);
+        }
+
+        let mut planned: HashMap<i64, BTreeSet<i64>> = HashMap::new();
+
+        for crew in &input.project_crew {
+            if let Some(crew_id) = crew.crew_id {
+                planned.entry(crew.project_id).or_default().insert(crew_id);
+            }
+        }
+
+        Self {
+            by_crew,
+            assignments,
+            planned,
+            names: input
+                .crew
+                .iter()
+                .map(|x| (x.id, x.displayname.as_str()))
+                .collect(),
+            unmapped: BTreeSet::new(),
+        }
+    }
+}
+
+/// Assigns the Harvest users of the crew planned on the project, and deactivates the user
+/// assignments created for crew that are no longer planned.
+fn plan_users(
+    plan: &mut SyncPlan,
+    users: &mut UserIndex,
+    mappings: &MappingStore,
+    project: ProjectRef,
+    project_name: &str,
+    rentman_project_id: i64,
+) {
+    // Harvest users by ID
+    let mut wanted: BTreeMap<i64, &harvest::User> = BTreeMap::new();
+
+    for crew_id in users.planned.get(&rentman_project_id).into_iter().flatten() {
+        match users.by_crew.get(crew_id) {
+            Some(user) => {
+                wanted.insert(user.id, user);
+            }
+            None => {
+                let name = users.names.get(crew_id).copied().unwrap_or("unknown");
+                users.unmapped.insert(format!("{} ({})", name, crew_id));
+            }
+        }
+    }
+
+    let assignments: &[&harvest::UserAssignment] = match project {
+        ProjectRef::Existing(id) => users.assignments.get(&id).map_or(&[], |x| x.as_slice()),
+        ProjectRef::New(_) => &[],
+    };
+
+    for user in wanted.values() {
+        let assignment = assignments.iter().find(|x| x.user.id == user.id);
+
+        match (assignment, project) {
+            (Some(assignment), ProjectRef::Existing(project_id)) => {
+                if !assignment.is_active && mappings.user_assignments.contains(&assignment.id) {
+                    plan.actions.push(Action::SetUserActive {
+                        project_id,
+                        project_name: project_name.to_string(),
+                        id: assignment.id,
+                        user_name: assignment.user.name.clone(),
+                        is_active: true,
+                    });
+                }
+            }
+            _ => plan.actions.push(Action::AssignUser {
+                project,
+                project_name: project_name.to_string(),
+                user_id: user.id,
+                user_name: format!("{} {}", user.first_name, user.last_name),
+            }),
+        }
+    }
+
+    let ProjectRef::Existing(project_id) = project else {
+        return;
+    };
+
+    for assignment in assignments {
+        if assignment.is_active
+            && mappings.user_assignments.contains(&assignment.id)
+            && !wanted.contains_key(&assignment.user.id)
+        {
+            plan.actions.push(Action::SetUserActive {
+                project_id,
+                project_name: project_name.to_string(),
+                id: assignment.id,
+                user_name: assignment.user.name.clone(),
+                is_active: false,
+            });
+        }
+    }
+}
+
+fn push_project_update(
+    plan: &mut SyncPlan,
+    harvest_project: &harvest::Project,
+    changes: Vec<ProjectChange>,
+) {
+    if changes.is_empty() {
+        return;
+    }
+
+    plan.actions.push(Action::UpdateProject {
+        id: harvest_project.id,
+        name: harvest_project.name.clone(),
+        changes,
+    });
+}
+
+impl fmt::Display for ClientRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientRef::Existing(id) => write!(f, "client {}", id),
+            ClientRef::New(rentman_id) => {
+                write!(f, "new client for Rentman contact {}", rentman_id)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ProjectRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectRef::Existing(id) => write!(f, "project {}", id),
+            ProjectRef::New(rentman_id) => {
+                write!(f, "new project for Rentman project {}", rentman_id)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.source {
+            _ if self.is_empty() => return write!(f, "none"),
+            BudgetSource::Hours => write!(f, "{} hours", self.amount)?,
+            BudgetSource::Fees => write!(f, "{:.2} in fees", self.amount)?,
+        }
+
+        match self.notify_at {
+            Some(percentage) => write!(f, ", notify at {}%", percentage),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::CreateClient { rentman_id, name } => {
+                write!(f, "Create client \"{}\" (Rentman {})", name, rentman_id)
+            }
+            Action::UpdateClient { name, changes, .. } => {
+                write!(f, "Update client \"{}\":", name)?;
+
+                for change in changes {
+                    match change {
+                        ClientChange::Rename { to } => write!(f, " rename to \"{}\";", to)?,
+                        ClientChange::Archive { reason } => write!(f, " archive ({});", reason)?,
+                    }
+                }
+
+                Ok(())
+            }
+            Action::CreateProject {
+                rentman_id,
+                name,
+                code,
+                client,
+                is_active,
+                ..
+            } => write!(
+                f,
+                "Create project \"{}\" (code {}, Rentman {}, {}, {})",
+                name,
+                code,
+                rentman_id,
+                client,
+                if *is_active { "active" } else { "archived" }
+            ),
+            Action::UpdateProject { name, changes, .. } => {
+                write!(f, "Update project \"{}\":", name)?;
+
+                for change in changes {
+                    match change {
+                        ProjectChange::Rename { to } => write!(f, " rename to \"{}\";", to)?,
+                        ProjectChange::Recode { from, to } => {
+                            write!(f, " change code from \"{}\" to \"{}\";", from, to)?
+                        }
+                        ProjectChange::Reassign { from, to } => {
+                            write!(f, " move from client {} to {};", from, to)?
+                        }
+                        ProjectChange::SetActive { is_active, reason } => write!(
+                            f,
+                            " {} ({});",
+                            if *is_active { "unarchive" } else { "archive" },
+                            reason
+                        )?,
+                        ProjectChange::SetBudget { to } => write!(f, " set budget to {};", to)?,
+                        ProjectChange::Reschedule { starts_on, ends_on } => {
+                            write!(f, " reschedule")?;
+
+                            if let Some(x) = starts_on {
+                                write!(f, " from {}", x)?;
+                            }
+
+                            if let Some(x) = ends_on {
+                                write!(f, " until {}", x)?;
+                            }
+
+                            write!(f, ";")?
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+            Action::CreateTask { name } => write!(f, "Create task \"{}\"", name),
+            Action::ReactivateTask { name, .. } => write!(f, "Reactivate task \"{}\"", name),
+            Action::AssignTask {
+                project_name,
+                task_name,
+                ..
+            } => write!(
+                f,
+                "Assign task \"{}\" to project \"{}\"",
+                task_name, project_name
+            ),
+            Action::SetTaskActive {
+                project_name,
+                task_name,
+                is_active,
+                ..
+            } => write!(
+                f,
+                "{} task \"{}\" on project \"{}\"",
+                if *is_active { "Activate" } else { "Deactivate" },
+                task_name,
+                project_name
+            ),
+            Action::AssignUser {
+                project_name,
+                user_name,
+                ..
+            } => write!(f, "Assign {} to project \"{}\"", user_name, project_name),
+            Action::SetUserActive {
+                project_name,
+                user_name,
+                is_active,
+                ..
+            } => write!(
+                f,
+                "{} {} on project \"{}\"",
+                if *is_active { "Activate" } else { "Deactivate" },
+                user_name,
+                project_name
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{
+        client, contact, crew, harvest_project, now, project_crew, rentman_project, subproject,
+        task, task_assignment, user, user_assignment,
+    };
+    use crate::rentman::Status;
+
+    const NVT_CLIENT: i64 = 99;
+
+    fn settings() -> Settings {
+        Settings {
+            nvt_client: NVT_CLIENT,
+            projects: ProjectRules::default(),
+            project_defaults: ProjectDefaults {
+                bill_by: "Project".to_string(),
+                budget_by: "none".to_string(),
+                is_billable: true,
+            },
+            archive: ArchiveRules::default(),
+            orphans: OrphanRules {
+                archive_clients: true,
+                keep_clients: vec![],
+                projects: OrphanAction::Report,
+                tag: "[verwijderd]".to_string(),
+            },
+            budget: None,
+            sync_tasks: false,
+            sync_users: false,
+            crew: HashMap::new(),
+            time_entries: None,
+        }
+    }
+
+    fn input() -> Input {
+        Input {
+            complete: false,
+            clients: vec![],
+            harvest_projects: vec![],
+            contacts: vec![],
+            rentman_projects: vec![],
+            subprojects: vec![],
+            statuses: rentman::StatusTable::default(),
+            functions: vec![],
+            tasks: vec![],
+            task_assignments: vec![],
+            users: vec![],
+            crew: vec![],
+            project_crew: vec![],
+            user_assignments: vec![],
+        }
+    }
+
+    #[test]
+    fn creates_client_and_its_project_in_one_plan() {
+        let mut input = input();
+        input.contacts = vec![contact(5, "Klant")];
+        input.rentman_projects = vec![
+            rentman_project(1, "Festival ", 5),
+            rentman_project(2, "Intern", 0),
+        ];
+
+        let plan = plan(&input, &MappingStore::default(), &settings(), now());
+
+        assert!(plan.warnings.is_empty());
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::CreateClient { rentman_id: 5, name: client_name },
+                Action::CreateProject {
+                    rentman_id: 1,
+                    name,
+                    code,
+                    client: ClientRef::New(5),
+                    is_active: true,
+                    ..
+                },
+                Action::CreateProject {
+                    rentman_id: 2,
+                    client: ClientRef::Existing(NVT_CLIENT),
+                    ..
+                },
+            ] if client_name == "Klant" && name == "Festival" && code == "1001"
+        ));
+    }
+
+    #[test]
+    fn warns_about_projects_of_unknown_customers() {
+        let mut input = input();
+        input.rentman_projects = vec![rentman_project(1, "Festival", 5)];
+
+        let plan = plan(&input, &MappingStore::default(), &settings(), now());
+
+        assert!(plan.actions.is_empty());
+        assert_eq!(
+            plan.warnings,
+            vec!["Client not found for project: Festival"]
+        );
+    }
+
+    #[test]
+    fn updates_mapped_records() {
+        let mut input = input();
+        input.contacts = vec![contact(5, "Klant"), contact(6, "Nieuwe naam")];
+        input.clients = vec![client(3, "Klant"), client(4, "Oude naam")];
+        input.rentman_projects = vec![rentman_project(1, "Festival", 6)];
+        input.harvest_projects = vec![harvest_project(10, "Oud festival", "999", 3)];
+
+        let mut mappings = MappingStore::default();
+        mappings.clients.extend([(5, 3), (6, 4)]);
+        mappings.projects.insert(1, 10);
+
+        let plan = plan(&input, &mappings, &settings(), now());
+
+        assert!(plan.warnings.is_empty());
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::UpdateClient { id: 4, changes: client_changes, .. },
+                Action::UpdateProject { id: 10, changes, .. },
+            ] if matches!(
+                client_changes.as_slice(),
+                [ClientChange::Rename { to }] if to == "Nieuwe naam"
+            ) && matches!(
+                changes.as_slice(),
+                [
+                    ProjectChange::Rename { to },
+                    ProjectChange::Recode { from, to: code },
+                    ProjectChange::Reassign { from: 3, to: ClientRef::Existing(4) },
+                ] if to == "Festival" && from == "999" && code == "1001"
+            )
+        ));
+    }
+
+    #[test]
+    fn reschedules_to_rentman_plan_period() {
+        let day = |x: &str| NaiveDate::parse_from_str(x, "%Y-%m-%d").unwrap();
+        let time = |x: &str| Some(DateTime::parse_from_rfc3339(x).unwrap());
+
+        let mut moved = rentman_project(1, "Festival", 0);
+        moved.planperiod_start = time("2026-03-05T08:00:00+01:00");
+        moved.planperiod_end = time("2026-03-06T23:30:00+01:00");
+
+        // Without an end in Rentman, Harvest keeps its own
+        let mut open_ended = rentman_project(2, "Beurs", 0);
+        open_ended.planperiod_start = time("2026-04-01T08:00:00+02:00");
+
+        let unplanned = rentman_project(3, "Opslag", 0);
+
+        let mut input = input();
+        input.rentman_projects = vec![moved, open_ended, unplanned];
+        input.harvest_projects = vec![
+            harvest_project(10, "Festival", "1001", NVT_CLIENT),
+            harvest_project(11, "Beurs", "1002", NVT_CLIENT),
+            harvest_project(12, "Opslag", "1003", NVT_CLIENT),
+        ];
+        input.harvest_projects[0].starts_on = Some(day("2026-03-01"));
+        input.harvest_projects[0].ends_on = Some(day("2026-03-06"));
+        input.harvest_projects[1].starts_on = Some(day("2026-04-01"));
+        input.harvest_projects[1].ends_on = Some(day("2026-04-03"));
+        input.harvest_projects[2].starts_on = Some(day("2026-01-01"));
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.extend([(1, 10), (2, 11), (3, 12)]);
+
+        let plan = plan(&input, &mappings, &settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [Action::UpdateProject { id: 10, changes, .. }] if matches!(
+                changes.as_slice(),
+                [ProjectChange::Reschedule { starts_on, ends_on }]
+                    if *starts_on == Some(day("2026-03-05")) && *ends_on == Some(day("2026-03-06"))
+            )
+        ));
+    }
+
+    #[test]
+    fn reassigns_to_new_client() {
+        let mut input = input();
+        input.contacts = vec![contact(6, "Klant")];
+        input.rentman_projects = vec![rentman_project(1, "Festival", 6)];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+
+        let plan = plan(&input, &mappings, &settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::CreateClient { rentman_id: 6, .. },
+                Action::UpdateProject { id: 10, changes, .. },
+            ] if matches!(
+                changes.as_slice(),
+                [ProjectChange::Reassign { from: NVT_CLIENT, to: ClientRef::New(6) }]
+            )
+        ));
+    }
+
+    #[test]
+    fn warns_about_unknown_status_names() {
+        let mut settings = settings();
+        settings
+            .archive
+            .statuses
+            .parse_actions("Bestaat niet=ignore")
+            .unwrap();
+
+        let plan = plan(&input(), &MappingStore::default(), &settings, now());
+
+        assert_eq!(
+            plan.warnings,
+            vec!["Status mapping refers to unknown Rentman status \"bestaat niet\""]
+        );
+    }
+
+    #[test]
+    fn archives_cancelled_project() {
+        let mut input = input();
+        input.rentman_projects = vec![rentman_project(1, "Festival", 0)];
+        input.subprojects = vec![subproject(1, 1, Status::GEANNULEERD)];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+
+        let plan = plan(&input, &mappings, &settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [Action::UpdateProject { id: 10, changes, .. }] if matches!(
+                changes.as_slice(),
+                [ProjectChange::SetActive { is_active: false, .. }]
+            )
+        ));
+    }
+
+    fn named_subproject(project_id: i64, order: i64, name: &str) -> rentman::SubprojectData {
+        let mut subproject = subproject(project_id, order, Status::BEVESTIGD);
+        subproject.name = name.to_string();
+        subproject
+    }
+
+    fn task_settings() -> Settings {
+        Settings {
+            sync_tasks: true,
+            ..settings()
+        }
+    }
+
+    /// Task names of the `AssignTask` actions, by project.
+    fn assigned(plan: &SyncPlan) -> Vec<(ProjectRef, String)> {
+        plan.actions
+            .iter()
+            .filter_map(|x| match x {
+                Action::AssignTask {
+                    project, task_name, ..
+                } => Some((*project, task_name.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn subprojects_with_the_same_name_share_a_task() {
+        let mut input = input();
+        input.rentman_projects = vec![
+            rentman_project(1, "Festival", 0),
+            rentman_project(2, "Beurs", 0),
+        ];
+        input.subprojects = vec![
+            named_subproject(1, 2, "opbouw "),
+            named_subproject(1, 1, "Opbouw"),
+            named_subproject(1, 3, "Show"),
+            named_subproject(2, 1, "OPBOUW"),
+        ];
+
+        let plan = plan(&input, &MappingStore::default(), &task_settings(), now());
+
+        let created: Vec<&str> = plan
+            .actions
+            .iter()
+            .filter_map(|x| match x {
+                Action::CreateTask { name } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(created, vec!["Opbouw", "Show"]);
+        assert_eq!(
+            assigned(&plan),
+            vec![
+                (ProjectRef::New(1), "Opbouw".to_string()),
+                (ProjectRef::New(1), "Show".to_string()),
+                (ProjectRef::New(2), "OPBOUW".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_renamed_and_removed_subprojects() {
+        let opbouw = task(30, "Opbouw", true);
+        let afbouw = task(31, "Afbouw", true);
+        let show = task(32, "Show", true);
+        let kantoor = task(33, "Kantoor", true);
+
+        let mut input = input();
+        input.rentman_projects = vec![rentman_project(1, "Festival", 0)];
+        input.subprojects = vec![
+            named_subproject(1, 1, "Opbouw"),
+            named_subproject(1, 2, "Show"),
+        ];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+        input.task_assignments = vec![
+            task_assignment(40, 10, &opbouw, true),
+            // Subproject "Afbouw" was renamed to "Show"
+            task_assignment(41, 10, &afbouw, true),
+            // Assigned by hand, so left alone
+            task_assignment(43, 10, &kantoor, true),
+        ];
+        input.tasks = vec![opbouw, afbouw, show, kantoor];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+        mappings.task_assignments.extend([40, 41]);
+
+        let plan = plan(&input, &mappings, &task_settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::AssignTask {
+                    project: ProjectRef::Existing(10),
+                    task: TaskRef::Existing(32),
+                    ..
+                },
+                Action::SetTaskActive {
+                    id: 41,
+                    is_active: false,
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn reactivates_task_assignments_and_archived_tasks() {
+        let opbouw = task(30, "Opbouw", true);
+        let show = task(32, "Show", false);
+
+        let mut input = input();
+        input.rentman_projects = vec![
+            rentman_project(1, "Festival", 0),
+            rentman_project(2, "Beurs", 0),
+        ];
+        input.subprojects = vec![
+            named_subproject(1, 1, "Opbouw"),
+            named_subproject(1, 2, "Show"),
+            named_subproject(2, 1, "Show"),
+        ];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+        input.task_assignments = vec![task_assignment(40, 10, &opbouw, false)];
+        input.tasks = vec![opbouw, show];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+        mappings.task_assignments.insert(40);
+
+        let plan = plan(&input, &mappings, &task_settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::SetTaskActive {
+                    id: 40,
+                    is_active: true,
+                    ..
+                },
+                Action::ReactivateTask { id: 32, .. },
+                Action::AssignTask {
+                    project: ProjectRef::Existing(10),
+                    task: TaskRef::Existing(32),
+                    ..
+                },
+                Action::CreateProject { rentman_id: 2, .. },
+                Action::AssignTask {
+                    project: ProjectRef::New(2),
+                    task: TaskRef::Existing(32),
+                    ..
+                },
+            ]
+        ));
+    }
+
+    fn user_settings() -> Settings {
+        Settings {
+            sync_users: true,
+            ..settings()
+        }
+    }
+
+    #[test]
+    fn assigns_users_of_planned_crew() {
+        let mut input = input();
+        input.rentman_projects = vec![
+            rentman_project(1, "Festival", 0),
+            rentman_project(2, "Beurs", 0),
+        ];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+        input.users = vec![user(50, "Anna", "Jansen"), user(51, "Bram", "Visser")];
+        input.users[1].is_active = false;
+        input.crew = vec![
+            crew(60, "Anna Jansen", Some(" Anna@Example.com")),
+            crew(61, "Bram Visser", Some("bram@example.com")),
+            crew(62, "Cees", None),
+        ];
+        input.project_crew = vec![
+            project_crew(1, 1, 60),
+            project_crew(2, 1, 61),
+            project_crew(3, 1, 62),
+            project_crew(4, 2, 60),
+        ];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+
+        let plan = plan(&input, &mappings, &user_settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::AssignUser {
+                    project: ProjectRef::Existing(10),
+                    user_id: 50,
+                    ..
+                },
+                Action::CreateProject { rentman_id: 2, .. },
+                Action::AssignUser {
+                    project: ProjectRef::New(2),
+                    user_id: 50,
+                    ..
+                },
+            ]
+        ));
+        assert_eq!(
+            plan.warnings,
+            [
+                "Rentman crew member Bram Visser (61) has no Harvest user, add their ID to crew",
+                "Rentman crew member Cees (62) has no Harvest user, add their ID to crew",
+            ]
+        );
+    }
+
+    #[test]
+    fn deactivates_assignments_of_unplanned_crew() {
+        let anna = user(50, "Anna", "Jansen");
+        let bram = user(51, "Bram", "Visser");
+        let manager = user(52, "Carla", "de Boer");
+
+        let mut input = input();
+        input.rentman_projects = vec![rentman_project(1, "Festival", 0)];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+        input.crew = vec![crew(60, "Anna Jansen", Some("anna@example.com"))];
+        input.project_crew = vec![project_crew(1, 1, 60)];
+        input.user_assignments = vec![
+            user_assignment(70, 10, &anna, false),
+            user_assignment(71, 10, &bram, true),
+            user_assignment(72, 10, &manager, true),
+        ];
+        input.users = vec![anna, bram, manager];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+        mappings.user_assignments.extend([70, 71]);
+
+        let plan = plan(&input, &mappings, &user_settings(), now());
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [
+                Action::SetUserActive {
+                    id: 70,
+                    is_active: true,
+                    ..
+                },
+                Action::SetUserActive {
+                    id: 71,
+                    is_active: false,
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn leaves_deactivated_and_untracked_assignments_alone() {
+        let anna = user(50, "Anna", "Jansen");
+        let bram = user(51, "Bram", "Visser");
+
+        let mut input = input();
+        input.rentman_projects = vec![rentman_project(1, "Festival", 0)];
+        input.harvest_projects = vec![harvest_project(10, "Festival", "1001", NVT_CLIENT)];
+        input.crew = vec![crew(60, "Anna Jansen", Some("anna@example.com"))];
+        input.project_crew = vec![project_crew(1, 1, 60)];
+        input.user_assignments = vec![
+            user_assignment(70, 10, &anna, false),
+            user_assignment(71, 10, &bram, false),
+        ];
+        input.users = vec![anna, bram];
+
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1, 10);
+        mappings.user_assignments.insert(71);
+
+        let plan = plan(&input, &mappings, &user_settings(), now());
+
+        assert!(plan.actions.is_empty());
+    }
+
+    /// A complete input where contacts 6 and 7 and project 2 were deleted in Rentman.
+    fn orphaned() -> (Input, MappingStore) {
+        let mut input = input();
+        input.complete = true;
+        input.clients = vec![client(8, "Weg"), client(9, "Weg met project")];
+        input.harvest_projects = vec![
+            harvest_project(11, "Verwijderd", "1002", 8),
+            harvest_project(12, "Lopend", "1003", 9),
+        ];
+        input.harvest_projects[0].is_active = false;
+
+        let mut mappings = MappingStore::default();
+        mappings.clients.extend([(7, 8), (6, 9)]);
+        mappings.projects.insert(2, 12);
+
+        (input, mappings)
+    }
+
+    #[test]
+    fn archives_orphaned_clients_without_active_projects() {
+        let (input, mappings) = orphaned();
+
+        let plan = plan(&input, &mappings, &settings(), now());
+
+        assert!(plan.actions.iter().any(|x| matches!(
+            x,
+            Action::UpdateClient { id: 8, changes, .. }
+                if matches!(changes.as_slice(), [ClientChange::Archive { .. }])
+        )));
+        assert!(!plan
+            .actions
+            .iter()
+            .any(|x| matches!(x, Action::UpdateClient { id: 9, .. })));
+        assert!(plan.warnings.contains(
+            &"Rentman contact of client Weg met project was deleted, but it still has 1 active projects"
+                .to_string()
+        ));
+
+        let mut settings = settings();
+        settings.orphans.keep_clients = vec![8];
+        let plan = super::plan(&input, &mappings, &settings, now());
+
+        assert!(!plan
+            .actions
+            .iter()
+            .any(|x| matches!(x, Action::UpdateClient { .. })));
+    }
+
+    #[test]
+    fn leaves_orphaned_clients_alone_on_partial_input() {
+        let (mut input, mappings) = orphaned();
+        input.complete = false;
+
+        let plan = plan(&input, &mappings, &settings(), now());
+
+        assert!(plan.actions.is_empty());
+    }
+
+    #[test]
+    fn handles_orphaned_projects() {
+        let (input, mappings) = orphaned();
+        let mut settings = settings();
+        settings.orphans.archive_clients = false;
+
+        let plan = super::plan(&input, &mappings, &settings, now());
+        assert!(plan.actions.is_empty());
+        assert!(plan.warnings.contains(
+            &"Rentman project 2 was deleted, but Harvest project Lopend is still active"
+                .to_string()
+        ));
+
+        settings.orphans.projects = OrphanAction::Tag;
+        let plan = super::plan(&input, &mappings, &settings, now());
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [Action::UpdateProject { id: 12, changes, .. }] if matches!(
+                changes.as_slice(),
+                [ProjectChange::Rename { to }] if to == "[verwijderd] Lopend"
+            )
+        ));
+
+        settings.orphans.projects = OrphanAction::Archive;
+        let plan = super::plan(&input, &mappings, &settings, now());
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [Action::UpdateProject { id: 12, changes, .. }] if matches!(
+                changes.as_slice(),
+                [ProjectChange::SetActive { is_active: false, .. }]
+            )
+        ));
+    }
+
+    #[test]
+    fn tags_orphaned_projects_once() {
+        let (mut input, mappings) = orphaned();
+        input.harvest_projects[1].name = "[verwijderd] Lopend".to_string();
+
+        let mut settings = settings();
+        settings.orphans.archive_clients = false;
+        settings.orphans.projects = OrphanAction::Tag;
+
+        let plan = plan(&input, &mappings, &settings, now());
+
+        assert!(plan.actions.is_empty());
+    }
+
+    #[test]
+    fn leaves_orphaned_projects_alone_on_partial_input() {
+        let (mut input, mappings) = orphaned();
+        input.complete = false;
+        let mut settings = settings();
+        settings.orphans.projects = OrphanAction::Archive;
+
+        let plan = plan(&input, &mappings, &settings, now());
+
+        assert!(plan.actions.is_empty());
+        assert!(plan.warnings.is_empty());
+    }
+}
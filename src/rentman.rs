@@ -1,99 +1,309 @@
-use reqwest::Error;
-use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, NaiveDate, SecondsFormat, TimeDelta, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::error::{self, SyncError};
+use crate::http::{HttpClient, RateLimit};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.rentman.net";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Rentman allows 10 requests per second.
+const RATE_LIMIT: RateLimit = RateLimit {
+    max_requests: 10,
+    window: Duration::from_secs(1),
+};
 
 pub struct RentmanClient {
-    token: String,
-    pub btdb_id: i64,
+    base_url: String,
+    /// Shared connection pool with the authentication header set on every request.
+    http: HttpClient,
 }
 
 impl RentmanClient {
-    pub fn new(token: String, btdb_id: i64) -> Self {
-        Self { token, btdb_id }
+    pub fn new(token: &str) -> Result<Self, SyncError> {
+        let mut authorization = HeaderValue::from_str(&format!("Bearer {}", token))
+            .map_err(|_| SyncError::Config("Invalid Rentman token".to_string()))?;
+        authorization.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, authorization);
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        Ok(Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            http: HttpClient::new(http, RATE_LIMIT),
+        })
     }
 
-    pub async fn get_contacts(&self) -> Result<Contacts, Error> {
-        let client = reqwest::Client::new();
+    /// Sends requests to another API root, e.g. a mock server or a recording proxy.
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
 
-        let res = client
-            .get("https://api.rentman.net/contacts")
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .send()
-            .await?
-            .json::<Contacts>()
-            .await?;
+    /// Fetches all contacts, or only those modified since `since`.
+    pub async fn get_contacts(&self, since: Option<DateTime<Utc>>) -> Result<Contacts, SyncError> {
+        self.get_all("/contacts", since).await
+    }
+
+    /// Fetches one contact, or `None` if it was deleted.
+    pub async fn get_contact(&self, id: i64) -> Result<Option<ContactsData>, SyncError> {
+        self.get_one(&format!("/contacts/{}", id)).await
+    }
 
-        if res.item_count >= res.limit {
-            panic!("Not all contacts are fetched");
+    /// Fetches all projects, or only those modified since `since`.
+    pub async fn get_projects(&self, since: Option<DateTime<Utc>>) -> Result<Projects, SyncError> {
+        let mut res: Projects = self.get_all("/projects", since).await?;
+
+        for project in &mut res.data {
+            project.resolve_customer();
         }
 
         Ok(res)
     }
 
-    pub async fn get_projects(&self) -> Result<Projects, Error> {
-        let client = reqwest::Client::new();
+    /// Fetches the projects whose plan period ended after `from`, up to `to`.
+    pub async fn get_projects_ended(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Projects, SyncError> {
+        let filters = [
+            (
+                "planperiod_end[gt]",
+                from.to_rfc3339_opts(SecondsFormat::Secs, true),
+            ),
+            (
+                "planperiod_end[lte]",
+                to.to_rfc3339_opts(SecondsFormat::Secs, true),
+            ),
+        ];
+
+        let mut res: Projects = self.get_filtered("/projects", &filters).await?;
 
-        let mut res = client
-            .get("https://api.rentman.net/projects")
-            .header("Authorization", format!("Bearer {}", &self.token))
-            .send()
-            .await?
-            .json::<Projects>()
+        for project in &mut res.data {
+            project.resolve_customer();
+        }
+
+        Ok(res)
+    }
+
+    /// Fetches one project, or `None` if it was deleted.
+    pub async fn get_project(&self, id: i64) -> Result<Option<ProjectsData>, SyncError> {
+        let mut project: Option<ProjectsData> = self.get_one(&format!("/projects/{}", id)).await?;
+
+        if let Some(project) = &mut project {
+            project.resolve_customer();
+        }
+
+        Ok(project)
+    }
+
+    pub async fn get_statuses(&self) -> Result<StatusTable, SyncError> {
+        let res: Statuses = self.get_all("/statuses", None).await?;
+
+        Ok(StatusTable::new(res.data))
+    }
+
+    /// Fetches all subprojects, or only those modified since `since`.
+    pub async fn get_subprojects(
+        &self,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<SubprojectData>, SyncError> {
+        let res: Subprojects = self.get_all("/subprojects", since).await?;
+
+        Ok(resolve_subprojects(res.data))
+    }
+
+    /// Fetches one subproject, or `None` if it was deleted.
+    pub async fn get_subproject(&self, id: i64) -> Result<Option<SubprojectData>, SyncError> {
+        let subproject: Option<SubprojectData> =
+            self.get_one(&format!("/subprojects/{}", id)).await?;
+
+        Ok(resolve_subprojects(subproject.into_iter().collect()).pop())
+    }
+
+    /// Fetches the subprojects of one project.
+    pub async fn get_project_subprojects(
+        &self,
+        project_id: i64,
+    ) -> Result<Vec<SubprojectData>, SyncError> {
+        let res: Subprojects = self
+            .get_all(&format!("/projects/{}/subprojects", project_id), None)
             .await?;
 
-        if res.item_count >= res.limit {
-            panic!("Not all projects are fetched");
+        Ok(resolve_subprojects(res.data))
+    }
+
+    /// Fetches a single item, which Rentman wraps in `data`.
+    async fn get_one<T: DeserializeOwned>(&self, path: &str) -> Result<Option<T>, SyncError> {
+        let res = self
+            .http
+            .send(self.http.get(format!("{}{}", self.base_url, path)))
+            .await?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
         }
 
-        for project in &mut res.data {
-            project.name = project.name.trim_end().to_string();
-
-            project.customer_id = project
-                .customer
-                .clone()
-                .unwrap_or("0".to_string())
-                .replace("/contacts/", "")
-                .parse::<i64>()
-                .unwrap_or(0);
+        let res = error::json::<Single<T>>(res).await?;
+
+        Ok(Some(res.data))
+    }
+
+    pub async fn get_crew(&self) -> Result<Crew, SyncError> {
+        self.get_all("/crew", None).await
+    }
+
+    /// Fetches the functions of all projects, or of one project only. Functions without a
+    /// project are left out.
+    pub async fn get_project_functions(
+        &self,
+        project_id: Option<i64>,
+    ) -> Result<Vec<ProjectFunctionData>, SyncError> {
+        let path = match project_id {
+            Some(id) => format!("/projects/{}/projectfunctions", id),
+            None => "/projectfunctions".to_string(),
+        };
+
+        let mut res: ProjectFunctions = self.get_all(&path, None).await?;
+
+        for function in &mut res.data {
+            function.resolve_project();
         }
 
-        Ok(res)
+        Ok(res.data.into_iter().filter(|x| x.project_id != 0).collect())
     }
 
-    // pub async fn get_statuses(&self) -> Result<Vec<StatusesData>, Error> {
-    //     let client = reqwest::Client::new();
+    /// Fetches the crew planned on all projects, or on one project only.
+    pub async fn get_project_crew(
+        &self,
+        project_id: Option<i64>,
+    ) -> Result<Vec<ProjectCrewData>, SyncError> {
+        if let Some(project_id) = project_id {
+            let mut res: ProjectCrew = self
+                .get_all(&format!("/projects/{}/projectcrew", project_id), None)
+                .await?;
 
-    //     let res = client
-    //         .get("https://api.rentman.net/statuses")
-    //         .header("Authorization", format!("Bearer {}", &self.token))
-    //         .send()
-    //         .await?
-    //         .json::<Statuses>()
-    //         .await?;
+            for crew in &mut res.data {
+                crew.project_id = project_id;
+                crew.resolve_crewmember();
+            }
 
-    //     if res.item_count >= res.limit {
-    //         panic!("Not all statuses are fetched");
-    //     }
+            return Ok(res.data);
+        }
 
-    //     return Ok(res.data);
-    // }
+        // Crew are planned on a function, which belongs to the project
+        let projects: HashMap<String, i64> = self
+            .get_project_functions(None)
+            .await?
+            .iter()
+            .map(|x| (format!("/projectfunctions/{}", x.id), x.project_id))
+            .collect();
+
+        let mut res: ProjectCrew = self.get_all("/projectcrew", None).await?;
+
+        for crew in &mut res.data {
+            crew.project_id = projects.get(&crew.function).copied().unwrap_or(0);
+            crew.resolve_crewmember();
+        }
+
+        Ok(res.data.into_iter().filter(|x| x.project_id != 0).collect())
+    }
+
+    pub async fn create_time_registration(
+        &self,
+        data: &TimeRegistration,
+    ) -> Result<TimeRegistrationData, SyncError> {
+        let req = self
+            .http
+            .post(format!("{}/timeregistration", self.base_url))
+            .json(data);
+
+        let res = self.http.send(req).await?;
+
+        Ok(error::json::<Single<TimeRegistrationData>>(res).await?.data)
+    }
 
-    pub async fn get_subprojects(&self) -> Result<Vec<SubprojectData>, Error> {
-        let client = reqwest::Client::new();
+    pub async fn update_time_registration(
+        &self,
+        id: i64,
+        data: &TimeRegistration,
+    ) -> Result<TimeRegistrationData, SyncError> {
+        let req = self
+            .http
+            .put(format!("{}/timeregistration/{}", self.base_url, id))
+            .json(data);
+
+        let res = self.http.send(req).await?;
+
+        Ok(error::json::<Single<TimeRegistrationData>>(res).await?.data)
+    }
+
+    /// Deletes a time registration. One that is already gone counts as deleted.
+    pub async fn delete_time_registration(&self, id: i64) -> Result<(), SyncError> {
+        let req = self
+            .http
+            .delete(format!("{}/timeregistration/{}", self.base_url, id));
+
+        let res = self.http.send(req).await?;
+
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
 
-        let mut data: Vec<SubprojectData> = vec![];
+        error::check(res).await?;
+
+        Ok(())
+    }
+
+    /// Fetches every item of a collection endpoint by walking `?offset=` until an empty page is returned.
+    /// With `since`, only items modified after it are fetched.
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Paginated<T>, SyncError> {
+        let filters: Vec<(&str, String)> = since
+            .map(|x| ("modified[gt]", x.to_rfc3339_opts(SecondsFormat::Secs, true)))
+            .into_iter()
+            .collect();
+
+        self.get_filtered(path, &filters).await
+    }
+
+    /// Fetches every item of a collection endpoint matching the `field[operator]=value` filters.
+    async fn get_filtered<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        filters: &[(&str, String)],
+    ) -> Result<Paginated<T>, SyncError> {
+        let mut data: Vec<T> = vec![];
         let mut offset = 0;
+        let mut limit = 0;
 
         loop {
-            let mut res = client
-                .get(format!(
-                    "https://api.rentman.net/subprojects?offset={}",
-                    offset
-                ))
-                .header("Authorization", format!("Bearer {}", &self.token))
-                .send()
-                .await?
-                .json::<Subprojects>()
-                .await?;
+            let req = self
+                .http
+                .get(format!("{}{}", self.base_url, path))
+                .query(&[("offset", offset)])
+                .query(filters);
+
+            let res = self.http.send(req).await?;
+
+            let mut res = error::json::<Paginated<T>>(res).await?;
 
             // If no more data, break
             if res.item_count == 0 {
@@ -102,20 +312,17 @@ impl RentmanClient {
 
             // Offset `offset` variable by length of `data`
             offset += res.item_count;
-
-            for subproject in &mut res.data {
-                subproject.project_id = subproject
-                    .project
-                    .clone()
-                    .replace("/projects/", "")
-                    .parse::<i64>()
-                    .expect("Subproject project_id parse error");
-            }
+            limit = res.limit;
 
             data.append(&mut res.data);
         }
 
-        Ok(data)
+        Ok(Paginated {
+            item_count: data.len() as i64,
+            data,
+            limit,
+            offset: 0,
+        })
     }
 
     // pub async fn get_equipment(&self) -> Result<Vec<EquipmentData>, Error> {
@@ -151,19 +358,28 @@ impl RentmanClient {
     // }
 }
 
-//
-// Contacts
-//
-
+/// A collection response. Returned by `get_all` with every page merged into `data`.
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-pub struct Contacts {
-    pub data: Vec<ContactsData>,
+pub struct Paginated<T> {
+    pub data: Vec<T>,
     pub item_count: i64,
     pub limit: i64,
     pub offset: i64,
 }
 
+/// A single item response.
+#[derive(Deserialize)]
+struct Single<T> {
+    data: T,
+}
+
+//
+// Contacts
+//
+
+pub type Contacts = Paginated<ContactsData>;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ContactsData {
     pub id: i64,
@@ -181,14 +397,7 @@ pub struct ContactsData {
 // Projects
 //
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Projects {
-    pub data: Vec<ProjectsData>,
-    pub item_count: i64,
-    pub limit: i64,
-    pub offset: i64,
-}
+pub type Projects = Paginated<ProjectsData>;
 
 #[derive(Serialize, Deserialize)]
 pub struct ProjectsData {
@@ -202,21 +411,60 @@ pub struct ProjectsData {
     /// Powerproductions ID
     pub number: i64,
     pub tags: String,
-    pub planperiod_start: Option<String>,
+    /// In the time zone of the Rentman account, so the dates are those of the show.
+    #[serde(default, deserialize_with = "plan_period")]
+    pub planperiod_start: Option<DateTime<FixedOffset>>,
+    #[serde(default, deserialize_with = "plan_period")]
+    pub planperiod_end: Option<DateTime<FixedOffset>>,
+    /// Price of the crew on the quote, excluding VAT.
+    #[serde(default)]
+    pub project_crew_price: f64,
+}
+
+impl ProjectsData {
+    /// Fills in `customer_id` and trims the name.
+    fn resolve_customer(&mut self) {
+        self.name = self.name.trim_end().to_string();
+
+        self.customer_id = self
+            .customer
+            .clone()
+            .unwrap_or("0".to_string())
+            .replace("/contacts/", "")
+            .parse::<i64>()
+            .unwrap_or(0);
+    }
+
+    /// End of the plan period, or its start if Rentman has no end.
+    pub fn planned_end(&self) -> Option<DateTime<Utc>> {
+        self.planperiod_end
+            .or(self.planperiod_start)
+            .map(|x| x.with_timezone(&Utc))
+    }
+
+    /// First and last day of the plan period.
+    pub fn plan_dates(&self) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        (
+            self.planperiod_start.map(|x| x.date_naive()),
+            self.planperiod_end.map(|x| x.date_naive()),
+        )
+    }
+}
+
+/// Reads a plan period boundary, treating a missing, empty or invalid value as unset.
+fn plan_period<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<FixedOffset>>, D::Error> {
+    let value = Option::<String>::deserialize(deserializer)?;
+
+    Ok(value.and_then(|x| DateTime::parse_from_rfc3339(x.trim()).ok()))
 }
 
 //
 // Statuses
 //
 
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Statuses {
-    pub data: Vec<StatusesData>,
-    pub item_count: i64,
-    pub limit: i64,
-    pub offset: i64,
-}
+pub type Statuses = Paginated<StatusesData>;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -230,20 +478,15 @@ pub struct StatusesData {
 // Subprojects
 //
 
-#[derive(Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Subprojects {
-    pub data: Vec<SubprojectData>,
-    pub item_count: i64,
-    pub limit: i64,
-    pub offset: i64,
-}
+pub type Subprojects = Paginated<SubprojectData>;
 
 #[derive(Serialize, Deserialize)]
 pub struct SubprojectData {
     pub id: i64,
     pub displayname: String,
-    pub project: String,
+    /// `/projects/{id}`
+    #[serde(default)]
+    pub project: Option<String>,
     /// Formatted from `project` without `/projects/`.
     #[serde(skip_deserializing)]
     pub project_id: i64,
@@ -253,26 +496,277 @@ pub struct SubprojectData {
     pub is_template: bool,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
-pub enum Status {
-    #[serde(rename = "/statuses/1")]
-    Optie,
-    #[serde(rename = "/statuses/2")]
-    Geannuleerd,
-    #[serde(rename = "/statuses/3")]
-    Bevestigd,
-    #[serde(rename = "/statuses/4")]
-    Klaargezet,
-    #[serde(rename = "/statuses/5")]
-    OpLocatie,
-    #[serde(rename = "/statuses/6")]
-    Retour,
-    #[serde(rename = "/statuses/7")]
-    Aanvraag,
-    #[serde(rename = "/statuses/8")]
-    Concept,
-    #[serde(rename = "/statuses/9")]
-    Factureren,
+impl SubprojectData {
+    /// Fills in `project_id`. Returns `false` when `project` is not a project reference.
+    fn resolve_project(&mut self) -> bool {
+        match self
+            .project
+            .as_deref()
+            .and_then(|x| x.strip_prefix("/projects/"))
+            .and_then(|x| x.parse().ok())
+        {
+            Some(id) => {
+                self.project_id = id;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Fills in the project of every subproject, logging and skipping those without a valid one,
+/// so one broken record doesn't stop the sync.
+fn resolve_subprojects(subprojects: Vec<SubprojectData>) -> Vec<SubprojectData> {
+    subprojects
+        .into_iter()
+        .filter_map(|mut x| {
+            if x.resolve_project() {
+                return Some(x);
+            }
+
+            eprintln!(
+                "Skipping Rentman subproject {}: can't decode project reference \"{}\"",
+                x.id,
+                x.project.unwrap_or_default()
+            );
+            None
+        })
+        .collect()
+}
+
+/// A reference to a Rentman status, deserialized from `/statuses/{id}`.
+///
+/// Accounts can add their own statuses, so any ID is accepted. Names are looked up in a
+/// `StatusTable` loaded from `/statuses`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Status(pub i64);
+
+impl Status {
+    pub const OPTIE: Status = Status(1);
+    pub const GEANNULEERD: Status = Status(2);
+    pub const BEVESTIGD: Status = Status(3);
+    pub const KLAARGEZET: Status = Status(4);
+    pub const OP_LOCATIE: Status = Status(5);
+    pub const RETOUR: Status = Status(6);
+    pub const AANVRAAG: Status = Status(7);
+    pub const CONCEPT: Status = Status(8);
+    pub const FACTUREREN: Status = Status(9);
+    /// Used for references that can't be parsed.
+    pub const UNKNOWN: Status = Status(0);
+
+    /// Name of the statuses every Rentman account starts with.
+    fn default_name(&self) -> Option<&'static str> {
+        let name = match *self {
+            Status::OPTIE => "Optie",
+            Status::GEANNULEERD => "Geannuleerd",
+            Status::BEVESTIGD => "Bevestigd",
+            Status::KLAARGEZET => "Klaargezet",
+            Status::OP_LOCATIE => "Op locatie",
+            Status::RETOUR => "Retour",
+            Status::AANVRAAG => "Aanvraag",
+            Status::CONCEPT => "Concept",
+            Status::FACTUREREN => "Factureren",
+            _ => return None,
+        };
+
+        Some(name)
+    }
+}
+
+impl Serialize for Status {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("/statuses/{}", self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Option::<String>::deserialize(deserializer)?;
+
+        let status = value
+            .as_deref()
+            .and_then(|x| x.strip_prefix("/statuses/"))
+            .and_then(|x| x.parse::<i64>().ok())
+            .map_or(Status::UNKNOWN, Status);
+
+        Ok(status)
+    }
+}
+
+/// Names of the statuses in the Rentman account, by ID.
+#[derive(Debug, Default)]
+pub struct StatusTable {
+    names: HashMap<Status, String>,
+}
+
+impl StatusTable {
+    pub fn new(statuses: Vec<StatusesData>) -> Self {
+        Self {
+            names: statuses
+                .into_iter()
+                .map(|x| (Status(x.id), x.displayname))
+                .collect(),
+        }
+    }
+
+    /// Looks up a status by its name in the account, ignoring case. Default names only match
+    /// statuses missing from the account, so a renamed status is only found by its new name.
+    pub fn find(&self, name: &str) -> Option<Status> {
+        let name = name.to_lowercase();
+
+        self.names
+            .iter()
+            .find(|(_, x)| x.to_lowercase() == name)
+            .map(|(status, _)| *status)
+            .or_else(|| {
+                (1..=9).map(Status).find(|x| {
+                    !self.names.contains_key(x)
+                        && x.default_name().is_some_and(|x| x.to_lowercase() == name)
+                })
+            })
+    }
+
+    /// Name of the status, falling back to the default names and then to its ID.
+    pub fn name(&self, status: Status) -> String {
+        if let Some(name) = self.names.get(&status) {
+            return name.clone();
+        }
+
+        match status.default_name() {
+            Some(name) => name.to_string(),
+            None if status == Status::UNKNOWN => "unknown status".to_string(),
+            None => format!("status {}", status.0),
+        }
+    }
+}
+
+//
+// Crew
+//
+
+pub type Crew = Paginated<CrewData>;
+
+#[derive(Serialize, Deserialize)]
+pub struct CrewData {
+    pub id: i64,
+    pub displayname: String,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+//
+// Project crew
+//
+
+pub type ProjectFunctions = Paginated<ProjectFunctionData>;
+
+/// A role to fill on a project, e.g. "Technician" for 3 people on the build-up day.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectFunctionData {
+    pub id: i64,
+    pub project: Option<String>,
+    /// Formatted from `project` without `/projects/`, `0` if it has none.
+    #[serde(skip_deserializing)]
+    pub project_id: i64,
+    /// `crew` or `transport`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Number of people needed.
+    pub quantity: f64,
+    #[serde(default, deserialize_with = "plan_period")]
+    pub planperiod_start: Option<DateTime<FixedOffset>>,
+    #[serde(default, deserialize_with = "plan_period")]
+    pub planperiod_end: Option<DateTime<FixedOffset>>,
+}
+
+impl ProjectFunctionData {
+    /// Fills in `project_id`.
+    fn resolve_project(&mut self) {
+        self.project_id = self
+            .project
+            .as_deref()
+            .and_then(|x| x.strip_prefix("/projects/"))
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0);
+    }
+
+    /// Hours of crew planned for this function: its shift times the number of shifts and people.
+    /// A plan period over several days is one shift a day from its start time to its end time,
+    /// or a night shift when the end time is earlier. Transport functions plan vehicles, not
+    /// crew, so they have none.
+    pub fn planned_hours(&self) -> f64 {
+        if self.kind.eq_ignore_ascii_case("transport") {
+            return 0.0;
+        }
+
+        let (Some(start), Some(end)) = (self.planperiod_start, self.planperiod_end) else {
+            return 0.0;
+        };
+
+        if end <= start {
+            return 0.0;
+        }
+
+        let days = (end.date_naive() - start.date_naive()).num_days();
+        let shift = end.time() - start.time();
+
+        let (shifts, shift) = if shift > TimeDelta::zero() {
+            (days + 1, shift)
+        } else {
+            (days, shift + TimeDelta::days(1))
+        };
+
+        (shifts * shift.num_minutes()) as f64 / 60.0 * self.quantity
+    }
+}
+
+pub type ProjectCrew = Paginated<ProjectCrewData>;
+
+/// A crew member planned on a function of a project.
+#[derive(Serialize, Deserialize)]
+pub struct ProjectCrewData {
+    pub id: i64,
+    pub function: String,
+    /// `/crew/{id}`, or `None` while the function isn't filled yet.
+    pub crewmember: Option<String>,
+    /// Resolved through `function`.
+    #[serde(skip_deserializing)]
+    pub project_id: i64,
+    /// Formatted from `crewmember` without `/crew/`.
+    #[serde(skip_deserializing)]
+    pub crew_id: Option<i64>,
+}
+
+impl ProjectCrewData {
+    /// Fills in `crew_id`.
+    fn resolve_crewmember(&mut self) {
+        self.crew_id = self
+            .crewmember
+            .as_deref()
+            .and_then(|x| x.strip_prefix("/crew/"))
+            .and_then(|x| x.parse().ok());
+    }
+}
+
+//
+// Time registration
+//
+
+/// Hours of a crew member on a project, as written to Rentman.
+#[derive(Serialize, Debug)]
+pub struct TimeRegistration {
+    /// `/crew/{id}`
+    pub crewmember: String,
+    /// `/projects/{id}`
+    pub project: String,
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub remark: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TimeRegistrationData {
+    pub id: i64,
 }
 
 //
@@ -296,3 +790,89 @@ pub enum Status {
 //     code: String,
 //     name: String,
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(kind: &str, quantity: f64, start: &str, end: &str) -> ProjectFunctionData {
+        ProjectFunctionData {
+            id: 1,
+            project: Some("/projects/1".to_string()),
+            project_id: 1,
+            kind: kind.to_string(),
+            quantity,
+            planperiod_start: Some(DateTime::parse_from_rfc3339(start).unwrap()),
+            planperiod_end: Some(DateTime::parse_from_rfc3339(end).unwrap()),
+        }
+    }
+
+    #[test]
+    fn planned_hours_of_one_shift() {
+        let function = function(
+            "crew",
+            2.0,
+            "2026-03-02T09:00:00+01:00",
+            "2026-03-02T17:30:00+01:00",
+        );
+
+        assert_eq!(function.planned_hours(), 17.0);
+    }
+
+    #[test]
+    fn planned_hours_count_a_shift_per_day() {
+        let function = function(
+            "crew",
+            1.0,
+            "2026-03-02T09:00:00+01:00",
+            "2026-03-04T18:00:00+01:00",
+        );
+
+        assert_eq!(function.planned_hours(), 27.0);
+    }
+
+    #[test]
+    fn planned_hours_of_overnight_shifts() {
+        let one_night = function(
+            "crew",
+            1.0,
+            "2026-03-02T22:00:00+01:00",
+            "2026-03-03T02:00:00+01:00",
+        );
+        let two_nights = function(
+            "crew",
+            1.0,
+            "2026-03-02T22:00:00+01:00",
+            "2026-03-04T02:00:00+01:00",
+        );
+
+        assert_eq!(one_night.planned_hours(), 4.0);
+        assert_eq!(two_nights.planned_hours(), 8.0);
+    }
+
+    #[test]
+    fn planned_hours_leave_out_transport_and_empty_periods() {
+        let transport = function(
+            "transport",
+            1.0,
+            "2026-03-02T09:00:00+01:00",
+            "2026-03-02T17:00:00+01:00",
+        );
+        let backwards = function(
+            "crew",
+            1.0,
+            "2026-03-02T17:00:00+01:00",
+            "2026-03-02T09:00:00+01:00",
+        );
+        let empty = function(
+            "crew",
+            1.0,
+            "2026-03-02T09:00:00+01:00",
+            "2026-03-02T09:00:00+01:00",
+        );
+
+        assert_eq!(transport.planned_hours(), 0.0);
+        assert_eq!(backwards.planned_hours(), 0.0);
+        assert_eq!(empty.planned_hours(), 0.0);
+    }
+}
@@ -0,0 +1,890 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::harvest;
+use crate::rentman::{self, Status, StatusTable};
+
+/// Which Rentman projects are synced.
+#[derive(Default)]
+pub struct ProjectRules {
+    /// Projects matching any of these are skipped.
+    pub exclude: Vec<ProjectRule>,
+    /// When not empty, only projects matching one of these are synced.
+    pub include: Vec<ProjectRule>,
+}
+
+/// Matches a project when every condition that is set matches.
+#[derive(Default)]
+pub struct ProjectRule {
+    /// Shown in the log when the rule skips a project.
+    pub name: String,
+    /// Rentman contact IDs of the customer, `0` for projects without one.
+    pub customers: Vec<i64>,
+    pub name_regex: Option<Regex>,
+    /// Lowercase Rentman tags, matching when the project has any of them.
+    pub tags: Vec<String>,
+    /// Lowercase Rentman status names, matching when any subproject has one of them.
+    pub statuses: Vec<String>,
+    /// Whether the last subproject is a template.
+    pub template_subproject: Option<bool>,
+    /// The plan period ended before this day.
+    pub ends_before: Option<NaiveDate>,
+    /// The plan period starts after this day.
+    pub starts_after: Option<NaiveDate>,
+}
+
+impl ProjectRules {
+    /// Why a project is not synced, or `None` when it is.
+    pub fn skip_reason(
+        &self,
+        project: &rentman::ProjectsData,
+        subprojects: &[&rentman::SubprojectData],
+        statuses: &StatusTable,
+    ) -> Option<String> {
+        if let Some(rule) = self
+            .exclude
+            .iter()
+            .find(|x| x.matches(project, subprojects, statuses))
+        {
+            return Some(format!("excluded by rule \"{}\"", rule.name));
+        }
+
+        if !self.include.is_empty()
+            && !self
+                .include
+                .iter()
+                .any(|x| x.matches(project, subprojects, statuses))
+        {
+            return Some("matches no include rule".to_string());
+        }
+
+        None
+    }
+
+    /// Status names in the rules that don't match any status in the Rentman account.
+    pub fn unknown_statuses(&self, statuses: &StatusTable) -> Vec<String> {
+        self.exclude
+            .iter()
+            .chain(&self.include)
+            .flat_map(|x| &x.statuses)
+            .filter(|x| statuses.find(x).is_none())
+            .cloned()
+            .collect()
+    }
+}
+
+impl ProjectRule {
+    /// A rule without conditions would match every project.
+    pub fn is_empty(&self) -> bool {
+        self.customers.is_empty()
+            && self.name_regex.is_none()
+            && self.tags.is_empty()
+            && self.statuses.is_empty()
+            && self.template_subproject.is_none()
+            && self.ends_before.is_none()
+            && self.starts_after.is_none()
+    }
+
+    fn matches(
+        &self,
+        project: &rentman::ProjectsData,
+        subprojects: &[&rentman::SubprojectData],
+        statuses: &StatusTable,
+    ) -> bool {
+        let (starts_on, ends_on) = project.plan_dates();
+
+        (self.customers.is_empty() || self.customers.contains(&project.customer_id))
+            && self
+                .name_regex
+                .as_ref()
+                .is_none_or(|x| x.is_match(&project.name))
+            && (self.tags.is_empty()
+                || project
+                    .tags
+                    .split(',')
+                    .any(|x| self.tags.contains(&x.trim().to_lowercase())))
+            && (self.statuses.is_empty()
+                || subprojects.iter().any(|x| {
+                    self.statuses
+                        .contains(&statuses.name(x.status).to_lowercase())
+                }))
+            && self
+                .template_subproject
+                .is_none_or(|x| subprojects.last().is_some_and(|y| y.is_template) == x)
+            && self
+                .ends_before
+                .is_none_or(|x| ends_on.or(starts_on).is_some_and(|y| y < x))
+            && self
+                .starts_after
+                .is_none_or(|x| starts_on.is_some_and(|y| y > x))
+    }
+}
+
+/// What happens to Harvest records whose Rentman record was deleted. Only checked on a full sync.
+pub struct OrphanRules {
+    /// Archive Harvest clients whose Rentman contact is gone and that have no active projects.
+    pub archive_clients: bool,
+    /// Harvest clients that are never archived, e.g. ones created by hand. The N.v.t. client
+    /// is always kept.
+    pub keep_clients: Vec<i64>,
+    pub projects: OrphanAction,
+    /// Put in front of the name of tagged projects.
+    pub tag: String,
+}
+
+/// What happens to a Harvest project whose Rentman project was deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrphanAction {
+    /// Only log it.
+    Report,
+    /// Put `OrphanRules::tag` in front of its name.
+    Tag,
+    Archive,
+}
+
+/// What a subproject status says about its project.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusAction {
+    Active,
+    /// Archived once the plan period ended, see `ArchiveRules::archive_after_days`.
+    Archived,
+    /// Archived right away, as the plan period won't happen.
+    Cancelled,
+    /// The status has no say, e.g. for subprojects that are still being quoted.
+    Ignore,
+}
+
+/// How the statuses of a project's subprojects are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Resolution {
+    /// Archive when all subprojects are archived.
+    All,
+    /// Archive when any subproject is archived.
+    Any,
+    /// The subproject with the highest `order` decides.
+    Latest,
+}
+
+/// Maps Rentman subproject statuses to Harvest's active/archived state.
+pub struct StatusRules {
+    /// Actions of the statuses every Rentman account starts with, by ID, so they hold whatever
+    /// the account calls them.
+    pub defaults: HashMap<Status, StatusAction>,
+    /// Configured actions by lowercase status name, overriding `defaults`.
+    pub actions: HashMap<String, StatusAction>,
+    /// Action for statuses in neither.
+    pub default: StatusAction,
+    pub resolution: Resolution,
+}
+
+impl Default for StatusRules {
+    fn default() -> Self {
+        Self {
+            defaults: HashMap::from([
+                (Status::GEANNULEERD, StatusAction::Cancelled),
+                (Status::RETOUR, StatusAction::Archived),
+                (Status::FACTUREREN, StatusAction::Archived),
+            ]),
+            actions: HashMap::new(),
+            default: StatusAction::Active,
+            resolution: Resolution::All,
+        }
+    }
+}
+
+impl StatusRules {
+    /// Resolves the configured names against the statuses of the Rentman account.
+    pub fn for_account<'a>(&'a self, statuses: &'a StatusTable) -> StatusActions<'a> {
+        let mut by_status = self.defaults.clone();
+        let mut unknown = vec![];
+
+        for (name, action) in &self.actions {
+            match statuses.find(name) {
+                Some(status) => {
+                    by_status.insert(status, *action);
+                }
+                None => unknown.push(name.clone()),
+            }
+        }
+
+        unknown.sort();
+
+        StatusActions {
+            rules: self,
+            statuses,
+            by_status,
+            unknown,
+        }
+    }
+
+    /// Overrides actions from a `Status=action` list, e.g. `Optie=ignore,Concept=ignore`.
+    pub fn parse_actions(&mut self, value: &str) -> Result<(), String> {
+        for pair in value.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+            let (status, action) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("\"{}\" is not a Status=action pair", pair))?;
+
+            self.actions
+                .insert(status.trim().to_lowercase(), action.trim().parse()?);
+        }
+
+        Ok(())
+    }
+}
+
+/// `StatusRules` for one Rentman account, with the configured names resolved to status IDs.
+pub struct StatusActions<'a> {
+    rules: &'a StatusRules,
+    pub statuses: &'a StatusTable,
+    by_status: HashMap<Status, StatusAction>,
+    /// Configured names that don't match any status in the account.
+    pub unknown: Vec<String>,
+}
+
+impl StatusActions<'_> {
+    pub fn action(&self, status: Status) -> StatusAction {
+        self.by_status
+            .get(&status)
+            .copied()
+            .unwrap_or(self.rules.default)
+    }
+
+    /// What the subprojects say about their project: `Active`, `Archived` or `Cancelled`, with a
+    /// reason for logging. Returns `None` when every subproject is ignored.
+    pub fn resolve(
+        &self,
+        subprojects: &[&rentman::SubprojectData],
+    ) -> Option<(StatusAction, String)> {
+        let relevant: Vec<(&rentman::SubprojectData, StatusAction)> = subprojects
+            .iter()
+            .map(|x| (*x, self.action(x.status)))
+            .filter(|(_, action)| *action != StatusAction::Ignore)
+            .collect();
+
+        let names = relevant
+            .iter()
+            .map(|(x, _)| self.statuses.name(x.status))
+            .collect::<Vec<String>>()
+            .join("/");
+
+        let all = |action: StatusAction| relevant.iter().all(|(_, x)| *x == action);
+        let any = |action: StatusAction| relevant.iter().any(|(_, x)| *x == action);
+
+        match self.rules.resolution {
+            Resolution::All => {
+                if relevant.is_empty() {
+                    return None;
+                }
+
+                let action = if all(StatusAction::Cancelled) {
+                    StatusAction::Cancelled
+                } else if !any(StatusAction::Active) {
+                    StatusAction::Archived
+                } else {
+                    StatusAction::Active
+                };
+
+                Some((action, format!("subprojects are {}", names)))
+            }
+            Resolution::Any => {
+                if relevant.is_empty() {
+                    return None;
+                }
+
+                let action = if any(StatusAction::Cancelled) {
+                    StatusAction::Cancelled
+                } else if any(StatusAction::Archived) {
+                    StatusAction::Archived
+                } else {
+                    StatusAction::Active
+                };
+
+                Some((action, format!("subprojects are {}", names)))
+            }
+            Resolution::Latest => {
+                let (latest, action) = relevant.iter().max_by_key(|(x, _)| x.order)?;
+
+                Some((
+                    *action,
+                    format!(
+                        "latest subproject \"{}\" is {}",
+                        latest.name,
+                        self.statuses.name(latest.status)
+                    ),
+                ))
+            }
+        }
+    }
+}
+
+impl FromStr for StatusAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "active" => Ok(StatusAction::Active),
+            "archived" => Ok(StatusAction::Archived),
+            "cancelled" => Ok(StatusAction::Cancelled),
+            "ignore" => Ok(StatusAction::Ignore),
+            _ => Err(format!(
+                "\"{}\" is not one of active, archived, cancelled or ignore",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for OrphanAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "report" => Ok(OrphanAction::Report),
+            "tag" => Ok(OrphanAction::Tag),
+            "archive" => Ok(OrphanAction::Archive),
+            _ => Err(format!("\"{}\" is not one of report, tag or archive", s)),
+        }
+    }
+}
+
+impl FromStr for BudgetSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hours" => Ok(BudgetSource::Hours),
+            "fees" => Ok(BudgetSource::Fees),
+            _ => Err(format!("\"{}\" is not one of hours or fees", s)),
+        }
+    }
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "all" => Ok(Resolution::All),
+            "any" => Ok(Resolution::Any),
+            "latest" => Ok(Resolution::Latest),
+            _ => Err(format!("\"{}\" is not one of all, any or latest", s)),
+        }
+    }
+}
+
+/// When a project is archived in Harvest, based on its Rentman plan period and subproject statuses.
+pub struct ArchiveRules {
+    /// A project is archived when its subproject statuses say so...
+    pub statuses: StatusRules,
+    /// ...and its plan period ended at least this many days ago.
+    pub archive_after_days: i64,
+    /// Archived projects are only reactivated when their plan period ended at most this many days ago,
+    /// so old projects that were archived by hand stay archived.
+    pub reactivate_within_days: i64,
+}
+
+impl Default for ArchiveRules {
+    fn default() -> Self {
+        Self {
+            statuses: StatusRules::default(),
+            archive_after_days: 0,
+            reactivate_within_days: 365,
+        }
+    }
+}
+
+impl ArchiveRules {
+    /// Derives whether a project should be active, with a reason for logging.
+    /// Returns `None` when the statuses of its subprojects are all ignored.
+    pub fn active_state(
+        &self,
+        project: &rentman::ProjectsData,
+        subprojects: &[&rentman::SubprojectData],
+        statuses: &StatusActions,
+        now: DateTime<Utc>,
+    ) -> Option<(bool, String)> {
+        if subprojects.is_empty() {
+            return Some((true, "project has no subprojects".to_string()));
+        }
+
+        let (action, reason) = statuses.resolve(subprojects)?;
+
+        match action {
+            StatusAction::Archived => {}
+            StatusAction::Cancelled => return Some((false, format!("{}, cancelled", reason))),
+            _ => return Some((true, reason)),
+        }
+
+        let Some(end) = project.planned_end() else {
+            return Some((false, format!("{}, no plan period", reason)));
+        };
+
+        let days = (now - end).num_days();
+
+        if days < self.archive_after_days {
+            return Some((
+                true,
+                format!(
+                    "{}, but plan period ended {} days ago, archiving after {}",
+                    reason, days, self.archive_after_days
+                ),
+            ));
+        }
+
+        Some((
+            false,
+            format!("{}, plan period ended {} days ago", reason, days),
+        ))
+    }
+
+    /// Whether the plan period is recent enough to reactivate an archived project.
+    pub fn may_reactivate(&self, project: &rentman::ProjectsData, now: DateTime<Utc>) -> bool {
+        project
+            .planned_end()
+            .is_some_and(|end| (now - end).num_days() <= self.reactivate_within_days)
+    }
+}
+
+/// Where Harvest project budgets come from in Rentman.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetSource {
+    /// Hours of crew planned on the project's functions.
+    Hours,
+    /// Price of the crew on the quote.
+    Fees,
+}
+
+/// How Harvest project budgets are set from Rentman.
+pub struct BudgetRules {
+    pub source: BudgetSource,
+    /// Have Harvest notify project managers at this percentage of the budget.
+    /// `None` leaves the notification as it is.
+    pub notify_at: Option<f64>,
+}
+
+/// A Harvest project budget.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Budget {
+    pub source: BudgetSource,
+    /// Hours or fees, depending on `source`.
+    pub amount: f64,
+    pub notify_at: Option<f64>,
+}
+
+impl BudgetRules {
+    /// The budget of a project, which is empty when Rentman has nothing planned or quoted.
+    pub fn budget(
+        &self,
+        project: &rentman::ProjectsData,
+        functions: &[&rentman::ProjectFunctionData],
+    ) -> Budget {
+        let amount = match self.source {
+            BudgetSource::Hours => functions.iter().map(|x| x.planned_hours()).sum(),
+            BudgetSource::Fees => project.project_crew_price,
+        };
+
+        // Harvest keeps two decimals
+        let amount = (amount * 100.0).round() / 100.0;
+
+        Budget {
+            source: self.source,
+            amount: amount.max(0.0),
+            notify_at: self.notify_at,
+        }
+    }
+}
+
+impl Budget {
+    /// Nothing is planned or quoted, so the project has no budget.
+    pub fn is_empty(&self) -> bool {
+        self.amount == 0.0
+    }
+
+    /// Harvest's `budget_by` for this budget.
+    pub fn budget_by(&self) -> &'static str {
+        match self.source {
+            _ if self.is_empty() => "none",
+            BudgetSource::Hours => "project",
+            BudgetSource::Fees => "project_cost",
+        }
+    }
+
+    /// Whether the Harvest project already has this budget.
+    pub fn matches(&self, project: &harvest::Project) -> bool {
+        if self.is_empty() {
+            return project.budget_by.as_deref().is_none_or(|x| x == "none");
+        }
+
+        let amount = match self.source {
+            BudgetSource::Hours => project.budget,
+            BudgetSource::Fees => project.cost_budget,
+        };
+
+        project.budget_by.as_deref() == Some(self.budget_by())
+            && amount.is_some_and(|x| (x - self.amount).abs() < 0.005)
+            && self.notify_at.is_none_or(|percentage| {
+                project.notify_when_over_budget
+                    && project
+                        .over_budget_notification_percentage
+                        .is_some_and(|x| (x - percentage).abs() < 0.005)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use super::*;
+    use crate::fixtures::{harvest_project, now, rentman_project, status, subproject};
+
+    /// A project of customer 5 that ended at `end`.
+    fn project(end: Option<&str>) -> rentman::ProjectsData {
+        let end = end.map(|x| DateTime::parse_from_rfc3339(x).unwrap());
+
+        let mut project = rentman_project(1, "Festival", 5);
+        project.tags = "festival, outdoor".to_string();
+        project.planperiod_start = end;
+        project.planperiod_end = end;
+        project
+    }
+
+    /// Statuses of an account that renamed Geannuleerd and added Gearchiveerd.
+    fn statuses() -> StatusTable {
+        StatusTable::new(vec![
+            status(2, "Cancelled"),
+            status(3, "Bevestigd"),
+            status(10, "Gearchiveerd"),
+        ])
+    }
+
+    fn resolve(
+        rules: &StatusRules,
+        subprojects: &[rentman::SubprojectData],
+    ) -> Option<StatusAction> {
+        let statuses = statuses();
+        let subprojects: Vec<&rentman::SubprojectData> = subprojects.iter().collect();
+
+        rules
+            .for_account(&statuses)
+            .resolve(&subprojects)
+            .map(|(action, _)| action)
+    }
+
+    fn rules(resolution: Resolution) -> StatusRules {
+        StatusRules {
+            resolution,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn resolve_all_archives_when_no_subproject_is_active() {
+        let rules = rules(Resolution::All);
+
+        let mixed = [
+            subproject(1, 1, Status::RETOUR),
+            subproject(1, 2, Status::BEVESTIGD),
+        ];
+        let done = [
+            subproject(1, 1, Status::RETOUR),
+            subproject(1, 2, Status::FACTUREREN),
+        ];
+        let partly_cancelled = [
+            subproject(1, 1, Status::GEANNULEERD),
+            subproject(1, 2, Status::RETOUR),
+        ];
+        let cancelled = [
+            subproject(1, 1, Status::GEANNULEERD),
+            subproject(1, 2, Status::GEANNULEERD),
+        ];
+
+        assert_eq!(resolve(&rules, &mixed), Some(StatusAction::Active));
+        assert_eq!(resolve(&rules, &done), Some(StatusAction::Archived));
+        assert_eq!(
+            resolve(&rules, &partly_cancelled),
+            Some(StatusAction::Archived)
+        );
+        assert_eq!(resolve(&rules, &cancelled), Some(StatusAction::Cancelled));
+    }
+
+    #[test]
+    fn resolve_any_archives_when_one_subproject_is_archived() {
+        let rules = rules(Resolution::Any);
+
+        let mixed = [
+            subproject(1, 1, Status::RETOUR),
+            subproject(1, 2, Status::BEVESTIGD),
+        ];
+        let partly_cancelled = [
+            subproject(1, 1, Status::GEANNULEERD),
+            subproject(1, 2, Status::BEVESTIGD),
+        ];
+        let active = [subproject(1, 1, Status::BEVESTIGD)];
+
+        assert_eq!(resolve(&rules, &mixed), Some(StatusAction::Archived));
+        assert_eq!(
+            resolve(&rules, &partly_cancelled),
+            Some(StatusAction::Cancelled)
+        );
+        assert_eq!(resolve(&rules, &active), Some(StatusAction::Active));
+    }
+
+    #[test]
+    fn resolve_latest_follows_highest_order() {
+        let rules = rules(Resolution::Latest);
+
+        let archived_last = [
+            subproject(1, 2, Status::RETOUR),
+            subproject(1, 1, Status::BEVESTIGD),
+        ];
+        let active_last = [
+            subproject(1, 1, Status::RETOUR),
+            subproject(1, 2, Status::BEVESTIGD),
+        ];
+
+        assert_eq!(
+            resolve(&rules, &archived_last),
+            Some(StatusAction::Archived)
+        );
+        assert_eq!(resolve(&rules, &active_last), Some(StatusAction::Active));
+    }
+
+    #[test]
+    fn resolve_leaves_out_ignored_statuses() {
+        let mut rules = rules(Resolution::All);
+        rules.parse_actions("Optie=ignore").unwrap();
+
+        let quoted = [subproject(1, 1, Status::OPTIE)];
+        let quoted_and_done = [
+            subproject(1, 1, Status::OPTIE),
+            subproject(1, 2, Status::RETOUR),
+        ];
+
+        assert_eq!(resolve(&rules, &quoted), None);
+        assert_eq!(
+            resolve(&rules, &quoted_and_done),
+            Some(StatusAction::Archived)
+        );
+    }
+
+    #[test]
+    fn for_account_resolves_names_and_reports_unknown_ones() {
+        let mut rules = rules(Resolution::All);
+        rules
+            .parse_actions("Gearchiveerd=archived,Geannuleerd=active,Bestaat niet=ignore")
+            .unwrap();
+
+        let statuses = statuses();
+        let actions = rules.for_account(&statuses);
+
+        assert_eq!(actions.action(Status(10)), StatusAction::Archived);
+        // Renamed in this account, so the default action holds and the old name is unknown
+        assert_eq!(actions.action(Status::GEANNULEERD), StatusAction::Cancelled);
+        assert_eq!(actions.action(Status::BEVESTIGD), StatusAction::Active);
+        assert_eq!(actions.unknown, vec!["bestaat niet", "geannuleerd"]);
+    }
+
+    #[test]
+    fn active_state_archives_after_days() {
+        let archive = ArchiveRules {
+            archive_after_days: 7,
+            ..Default::default()
+        };
+        let statuses = statuses();
+        let actions = archive.statuses.for_account(&statuses);
+
+        let done = subproject(1, 1, Status::RETOUR);
+        let subprojects = [&done];
+
+        let recent = project(Some("2026-02-27T23:00:00+01:00"));
+        let old = project(Some("2026-02-01T23:00:00+01:00"));
+        let unplanned = project(None);
+
+        let state = |project| archive.active_state(project, &subprojects, &actions, now());
+
+        assert_eq!(state(&recent).map(|x| x.0), Some(true));
+        assert_eq!(state(&old).map(|x| x.0), Some(false));
+        assert_eq!(state(&unplanned).map(|x| x.0), Some(false));
+    }
+
+    #[test]
+    fn active_state_archives_cancelled_projects_right_away() {
+        let archive = ArchiveRules {
+            archive_after_days: 7,
+            ..Default::default()
+        };
+        let statuses = statuses();
+        let actions = archive.statuses.for_account(&statuses);
+
+        let cancelled = subproject(1, 1, Status::GEANNULEERD);
+        let upcoming = project(Some("2026-06-01T23:00:00+02:00"));
+
+        assert_eq!(
+            archive
+                .active_state(&upcoming, &[&cancelled], &actions, now())
+                .map(|x| x.0),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn active_state_keeps_projects_without_subprojects_active() {
+        let archive = ArchiveRules::default();
+        let statuses = statuses();
+        let actions = archive.statuses.for_account(&statuses);
+
+        let old = project(Some("2020-01-01T23:00:00+01:00"));
+
+        assert_eq!(
+            archive
+                .active_state(&old, &[], &actions, now())
+                .map(|x| x.0),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn may_reactivate_only_recent_projects() {
+        let archive = ArchiveRules {
+            reactivate_within_days: 30,
+            ..Default::default()
+        };
+
+        assert!(archive.may_reactivate(&project(Some("2026-02-20T23:00:00+01:00")), now()));
+        assert!(!archive.may_reactivate(&project(Some("2025-12-01T23:00:00+01:00")), now()));
+        assert!(!archive.may_reactivate(&project(None), now()));
+    }
+
+    #[test]
+    fn skip_reason_applies_exclude_before_include() {
+        let statuses = statuses();
+        let confirmed = subproject(1, 1, Status::BEVESTIGD);
+        let festival = project(None);
+
+        let skip = |rules: &ProjectRules| rules.skip_reason(&festival, &[&confirmed], &statuses);
+
+        assert_eq!(skip(&ProjectRules::default()), None);
+
+        let excluded = ProjectRules {
+            exclude: vec![ProjectRule {
+                name: "outdoor".to_string(),
+                tags: vec!["outdoor".to_string()],
+                ..Default::default()
+            }],
+            include: vec![ProjectRule {
+                customers: vec![5],
+                ..Default::default()
+            }],
+        };
+        assert_eq!(
+            skip(&excluded),
+            Some("excluded by rule \"outdoor\"".to_string())
+        );
+
+        let included = ProjectRules {
+            include: vec![ProjectRule {
+                statuses: vec!["bevestigd".to_string()],
+                name_regex: Some(Regex::new("^Fest").unwrap()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(skip(&included), None);
+
+        let not_included = ProjectRules {
+            include: vec![ProjectRule {
+                customers: vec![0],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            skip(&not_included),
+            Some("matches no include rule".to_string())
+        );
+    }
+
+    #[test]
+    fn rule_matches_template_and_plan_period() {
+        let statuses = statuses();
+        let mut template = subproject(1, 2, Status::BEVESTIGD);
+        template.is_template = true;
+        let festival = project(Some("2026-02-01T23:00:00+01:00"));
+
+        let matches = |rule: ProjectRule| rule.matches(&festival, &[&template], &statuses);
+
+        assert!(matches(ProjectRule {
+            template_subproject: Some(true),
+            ..Default::default()
+        }));
+        assert!(!matches(ProjectRule {
+            template_subproject: Some(false),
+            ..Default::default()
+        }));
+        assert!(matches(ProjectRule {
+            ends_before: NaiveDate::from_ymd_opt(2026, 2, 2),
+            ..Default::default()
+        }));
+        assert!(!matches(ProjectRule {
+            starts_after: NaiveDate::from_ymd_opt(2026, 2, 1),
+            ..Default::default()
+        }));
+        assert!(!matches(ProjectRule {
+            customers: vec![5],
+            tags: vec!["indoor".to_string()],
+            ..Default::default()
+        }));
+    }
+
+    #[test]
+    fn empty_budget_clears_harvest_budget() {
+        let rules = BudgetRules {
+            source: BudgetSource::Fees,
+            notify_at: None,
+        };
+        let budget = rules.budget(&project(None), &[]);
+
+        let mut harvest_project = harvest_project(7, "Festival", "1001", 3);
+        harvest_project.budget_by = Some("project_cost".to_string());
+        harvest_project.cost_budget = Some(1200.0);
+
+        assert!(budget.is_empty());
+        assert_eq!(budget.budget_by(), "none");
+        assert!(!budget.matches(&harvest_project));
+
+        harvest_project.budget_by = Some("none".to_string());
+        assert!(budget.matches(&harvest_project));
+    }
+
+    #[test]
+    fn budget_matches_within_a_cent() {
+        let mut festival = project(None);
+        festival.project_crew_price = 1234.567;
+
+        let rules = BudgetRules {
+            source: BudgetSource::Fees,
+            notify_at: Some(80.0),
+        };
+        let budget = rules.budget(&festival, &[]);
+
+        let mut harvest_project = harvest_project(7, "Festival", "1001", 3);
+        harvest_project.budget_by = Some("project_cost".to_string());
+        harvest_project.cost_budget = Some(1234.57);
+
+        assert_eq!(budget.amount, 1234.57);
+        assert!(!budget.matches(&harvest_project));
+
+        harvest_project.notify_when_over_budget = true;
+        harvest_project.over_budget_notification_percentage = Some(80.0);
+        assert!(budget.matches(&harvest_project));
+    }
+}
@@ -0,0 +1,200 @@
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use chrono::Local;
+use tokio::sync::watch;
+
+/// When `serve` runs a sync.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// A sync every interval, starting right away. A sync that takes longer delays the next one.
+    Interval(Duration),
+    /// A sync at the times of a cron expression, in local time.
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    pub fn parse_interval(value: &str) -> Result<Self, String> {
+        let interval = parse_duration(value)?;
+
+        if interval.is_zero() {
+            return Err("interval must be at least 1 second".to_string());
+        }
+
+        Ok(Schedule::Interval(interval))
+    }
+
+    /// Parses a cron expression. The standard five fields are accepted as well as the cron
+    /// crate's six or seven, which start with seconds.
+    pub fn parse_cron(value: &str) -> Result<Self, String> {
+        let expression = match value.split_whitespace().count() {
+            5 => format!("0 {}", value.trim()),
+            _ => value.trim().to_string(),
+        };
+
+        cron::Schedule::from_str(&expression)
+            .map(|x| Schedule::Cron(Box::new(x)))
+            .map_err(|e| format!("\"{}\" is not a valid cron expression: {}", value, e))
+    }
+
+    /// Time to wait before the first sync.
+    pub fn first_delay(&self) -> Duration {
+        match self {
+            Schedule::Interval(_) => Duration::ZERO,
+            Schedule::Cron(schedule) => until_next(schedule),
+        }
+    }
+
+    /// Time to wait before the next sync, given when the last one started.
+    /// Cron times that passed during a sync are skipped, so runs never overlap.
+    pub fn next_delay(&self, started: Instant) -> Duration {
+        match self {
+            Schedule::Interval(interval) => interval.saturating_sub(started.elapsed()),
+            Schedule::Cron(schedule) => until_next(schedule),
+        }
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Interval(interval) => write!(f, "every {} seconds", interval.as_secs()),
+            Schedule::Cron(schedule) => write!(f, "at \"{}\"", schedule),
+        }
+    }
+}
+
+fn until_next(schedule: &cron::Schedule) -> Duration {
+    schedule
+        .upcoming(Local)
+        .next()
+        .and_then(|x| (x - Local::now()).to_std().ok())
+        .unwrap_or(Duration::ZERO)
+}
+
+/// Parses durations like `900`, `90s`, `15m`, `1h` or `1d`. Plain numbers are seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|x: char| !x.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let multiplier = match unit.trim() {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => 0,
+    };
+
+    match number.parse::<u64>() {
+        Ok(number) if multiplier > 0 => Ok(Duration::from_secs(number * multiplier)),
+        _ => Err(format!(
+            "\"{}\" is not a duration like 90s, 15m or 1h",
+            value
+        )),
+    }
+}
+
+/// Set on SIGTERM or Ctrl-C. The executor checks it between writes, so a running sync
+/// stops after its current write. A second signal exits immediately.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    /// Starts listening for SIGTERM and Ctrl-C.
+    pub fn listen() -> Self {
+        let (tx, rx) = watch::channel(false);
+
+        tokio::spawn(async move {
+            wait_for_signal().await;
+
+            println!("Shutting down after the current write...");
+            tx.send_replace(true);
+
+            wait_for_signal().await;
+
+            eprintln!("Shutting down immediately");
+            std::process::exit(130);
+        });
+
+        Self(rx)
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Completes once a shutdown is requested.
+    pub async fn requested(&mut self) {
+        let _ = self.0.wait_for(|x| *x).await;
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm = signal(SignalKind::terminate()).expect("Can't listen for SIGTERM");
+
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("900"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration(" 15 m "), Ok(Duration::from_secs(15 * 60)));
+        assert_eq!(parse_duration("1h"), Ok(Duration::from_secs(60 * 60)));
+        assert_eq!(
+            parse_duration("2d"),
+            Ok(Duration::from_secs(2 * 24 * 60 * 60))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for value in ["", "m", "1w", "-5m", "1.5h", "15min"] {
+            assert!(parse_duration(value).is_err(), "{:?}", value);
+        }
+
+        assert!(Schedule::parse_interval("0s").is_err());
+    }
+
+    #[test]
+    fn adds_seconds_to_five_field_cron() {
+        let Ok(Schedule::Cron(schedule)) = Schedule::parse_cron(" */15 6-22 * * Mon-Fri ") else {
+            panic!("not parsed");
+        };
+
+        assert_eq!(schedule.to_string(), "0 */15 6-22 * * Mon-Fri");
+    }
+
+    #[test]
+    fn keeps_six_and_seven_field_cron() {
+        let Ok(Schedule::Cron(six)) = Schedule::parse_cron("30 0 3 * * *") else {
+            panic!("not parsed");
+        };
+        let Ok(Schedule::Cron(seven)) = Schedule::parse_cron("30 0 3 * * * 2027") else {
+            panic!("not parsed");
+        };
+
+        assert_eq!(six.to_string(), "30 0 3 * * *");
+        assert_eq!(seven.to_string(), "30 0 3 * * * 2027");
+        assert!(Schedule::parse_cron("0 3 * *").is_err());
+        assert!(Schedule::parse_cron("0 25 * * *").is_err());
+    }
+}
@@ -0,0 +1,447 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, TimeDelta, Utc};
+use tokio::sync::Mutex;
+
+use crate::error::SyncError;
+use crate::executor::{self, Failures};
+use crate::harvest::{ClientData, HarvestClient};
+use crate::mapping::MappingStore;
+use crate::plan::{self, Input};
+use crate::rentman::{ContactsData, RentmanClient};
+use crate::rules::BudgetSource;
+use crate::schedule::Shutdown;
+use crate::time_entries;
+
+/// Changes are fetched from a bit before the last sync, in case a clock is off.
+const INCREMENTAL_OVERLAP: TimeDelta = TimeDelta::minutes(5);
+
+/// Runs full, incremental and targeted syncs. Runs hold the mapping store for their whole
+/// duration, so they never overlap.
+pub struct Syncer {
+    pub harvest: HarvestClient,
+    pub rentman: RentmanClient,
+    pub settings: plan::Settings,
+    pub dry_run: bool,
+    /// Sync only changed records, with a full sync at least this often. `None` always syncs everything.
+    pub full_sync_every: Option<Duration>,
+    mappings: Mutex<MappingStore>,
+}
+
+/// Rentman records to sync, by ID.
+#[derive(Debug, Default)]
+pub struct Targets {
+    pub projects: BTreeSet<i64>,
+    /// Synced through their project.
+    pub subprojects: BTreeSet<i64>,
+    pub contacts: BTreeSet<i64>,
+}
+
+impl Targets {
+    pub fn is_empty(&self) -> bool {
+        self.projects.is_empty() && self.subprojects.is_empty() && self.contacts.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Targets) {
+        self.projects.extend(other.projects);
+        self.subprojects.extend(other.subprojects);
+        self.contacts.extend(other.contacts);
+    }
+}
+
+impl Syncer {
+    pub fn new(
+        harvest: HarvestClient,
+        rentman: RentmanClient,
+        mappings: MappingStore,
+        settings: plan::Settings,
+        dry_run: bool,
+        full_sync_every: Option<Duration>,
+    ) -> Self {
+        Self {
+            harvest,
+            rentman,
+            settings,
+            dry_run,
+            full_sync_every,
+            mappings: Mutex::new(mappings),
+        }
+    }
+
+    /// Whether the mapping file was just started, so webhooks wait for a full sync to adopt the
+    /// legacy records.
+    pub async fn is_new(&self) -> bool {
+        self.mappings.lock().await.created
+    }
+
+    /// Syncs the records changed since the last sync, or everything when `full` is set, no sync
+    /// succeeded yet, or the last full sync is older than `full_sync_every`. Then writes
+    /// Harvest time entries to Rentman, if enabled.
+    /// Returns the number of changes that failed to apply.
+    ///
+    /// The Rentman records of failed Harvest changes are stored in the mapping file and synced
+    /// again by the next sync, so a change Harvest keeps refusing doesn't hold back later syncs.
+    pub async fn run(&self, full: bool, shutdown: &Shutdown) -> Result<usize, SyncError> {
+        let mut mappings = self.mappings.lock().await;
+        let started = Utc::now();
+
+        let since = match self.full_sync_every {
+            Some(every) if !full => mappings.synced_at.filter(|_| {
+                mappings
+                    .full_synced_at
+                    .is_some_and(|x| (started - x).to_std().is_ok_and(|x| x < every))
+            }),
+            _ => None,
+        };
+
+        let failures = match since {
+            Some(since) => {
+                println!(
+                    "Syncing changes since {}",
+                    since.to_rfc3339_opts(SecondsFormat::Secs, true)
+                );
+                self.incremental(&mut mappings, since - INCREMENTAL_OVERLAP, shutdown)
+                    .await?
+            }
+            None => self.full(&mut mappings, shutdown).await?,
+        };
+
+        let failed = match &self.settings.time_entries {
+            Some(settings) if !shutdown.is_requested() => {
+                failures.count
+                    + time_entries::sync(
+                        &self.harvest,
+                        &self.rentman,
+                        &mut mappings,
+                        settings,
+                        &self.settings.crew,
+                        self.dry_run,
+                        shutdown,
+                    )
+                    .await?
+            }
+            _ => failures.count,
+        };
+
+        if !shutdown.is_requested() && !self.dry_run {
+            mappings.synced_at = Some(started);
+
+            if since.is_none() {
+                mappings.full_synced_at = Some(started);
+            }
+
+            mappings.retry_contacts = failures.retry.contacts;
+            mappings.retry_projects = failures.retry.projects;
+            mappings.save()?;
+        }
+
+        Ok(failed)
+    }
+
+    /// Syncs the given Rentman records, e.g. after a webhook. Returns the number of changes that
+    /// failed to apply, whose records are synced again by the next sync.
+    pub async fn targets(&self, targets: Targets, shutdown: &Shutdown) -> Result<usize, SyncError> {
+        let mut mappings = self.mappings.lock().await;
+
+        // Legacy records are only adopted by a full sync, until then they would be created again
+        if mappings.created {
+            println!("Waiting for the first full sync, skipping {:?}", targets);
+            return Ok(0);
+        }
+
+        let failures = self.targeted(&mut mappings, targets, shutdown).await?;
+
+        if failures.count > 0 && !self.dry_run {
+            mappings.retry_contacts.extend(failures.retry.contacts);
+            mappings.retry_projects.extend(failures.retry.projects);
+            mappings.save()?;
+        }
+
+        Ok(failures.count)
+    }
+
+    async fn full(
+        &self,
+        mappings: &mut MappingStore,
+        shutdown: &Shutdown,
+    ) -> Result<Failures, SyncError> {
+        let mut input = Input {
+            complete: true,
+            clients: self.harvest.get_clients(None).await?,
+            harvest_projects: self.harvest.get_projects(None).await?,
+            contacts: self.rentman.get_contacts(None).await?.data,
+            rentman_projects: self.rentman.get_projects(None).await?.data,
+            subprojects: self.rentman.get_subprojects(None).await?,
+            statuses: self.rentman.get_statuses().await?,
+            functions: vec![],
+            tasks: vec![],
+            task_assignments: vec![],
+            users: vec![],
+            crew: vec![],
+            project_crew: vec![],
+            user_assignments: vec![],
+        };
+
+        if self.budget_by_hours() {
+            input.functions = self.rentman.get_project_functions(None).await?;
+        }
+
+        if self.settings.sync_tasks {
+            input.tasks = self.harvest.get_tasks().await?;
+            input.task_assignments = self.harvest.get_task_assignments(None).await?;
+        }
+
+        if self.settings.sync_users {
+            input.users = self.harvest.get_users().await?;
+            input.crew = self.rentman.get_crew().await?.data;
+            input.project_crew = self.rentman.get_project_crew(None).await?;
+            input.user_assignments = self.harvest.get_user_assignments(None).await?;
+        }
+
+        if mappings.created {
+            let adopted = mappings.bootstrap(&input.clients, &input.harvest_projects);
+
+            println!("Adopted {} legacy mappings from Harvest", adopted);
+
+            if !self.dry_run {
+                mappings.save()?;
+                mappings.created = false;
+            }
+        }
+
+        self.apply(mappings, input, shutdown).await
+    }
+
+    /// Syncs the records changed in Rentman or Harvest since `since`, the projects that are due
+    /// to be archived, and the records whose changes failed before. Harvest records changed by
+    /// someone else than this tool are synced too, so edits made by hand are corrected.
+    async fn incremental(
+        &self,
+        mappings: &mut MappingStore,
+        since: DateTime<Utc>,
+        shutdown: &Shutdown,
+    ) -> Result<Failures, SyncError> {
+        let mut targets = Targets::default();
+
+        if !mappings.retry_contacts.is_empty() || !mappings.retry_projects.is_empty() {
+            println!(
+                "Retrying {} contacts and {} projects that failed before",
+                mappings.retry_contacts.len(),
+                mappings.retry_projects.len()
+            );
+        }
+
+        targets.contacts.extend(&mappings.retry_contacts);
+        targets.projects.extend(&mappings.retry_projects);
+
+        targets.contacts.extend(
+            self.rentman
+                .get_contacts(Some(since))
+                .await?
+                .data
+                .iter()
+                .map(|x| x.id),
+        );
+        targets.projects.extend(
+            self.rentman
+                .get_projects(Some(since))
+                .await?
+                .data
+                .iter()
+                .map(|x| x.id),
+        );
+        targets.projects.extend(
+            self.rentman
+                .get_subprojects(Some(since))
+                .await?
+                .iter()
+                .map(|x| x.project_id),
+        );
+
+        // Archival waits for the plan period to end, which doesn't modify the project
+        let after_days = TimeDelta::days(self.settings.archive.archive_after_days);
+
+        targets.projects.extend(
+            self.rentman
+                .get_projects_ended(since - after_days, Utc::now() - after_days)
+                .await?
+                .data
+                .iter()
+                .map(|x| x.id)
+                .filter(|x| mappings.projects.contains_key(x)),
+        );
+
+        let contacts_by_client = mappings.contacts_by_client();
+        let rentman_projects_by_project = mappings.rentman_projects_by_project();
+
+        targets.contacts.extend(
+            self.harvest
+                .get_clients(Some(since))
+                .await?
+                .iter()
+                .filter(|x| !mappings.wrote_client(x))
+                .filter_map(|x| contacts_by_client.get(&x.id)),
+        );
+        targets.projects.extend(
+            self.harvest
+                .get_projects(Some(since))
+                .await?
+                .iter()
+                .filter(|x| !mappings.wrote_project(x))
+                .filter_map(|x| rentman_projects_by_project.get(&x.id)),
+        );
+
+        println!(
+            "{} contacts and {} projects changed",
+            targets.contacts.len(),
+            targets.projects.len()
+        );
+
+        if targets.is_empty() {
+            return Ok(Failures::default());
+        }
+
+        self.targeted(mappings, targets, shutdown).await
+    }
+
+    /// Syncs only the given records. Projects are synced with their customer, subprojects and crew.
+    async fn targeted(
+        &self,
+        mappings: &mut MappingStore,
+        targets: Targets,
+        shutdown: &Shutdown,
+    ) -> Result<Failures, SyncError> {
+        let mut project_ids = targets.projects;
+
+        for id in targets.subprojects {
+            match self.rentman.get_subproject(id).await? {
+                Some(subproject) => {
+                    project_ids.insert(subproject.project_id);
+                }
+                None => println!("Rentman subproject {} no longer exists, skipping", id),
+            }
+        }
+
+        let mut rentman_projects = vec![];
+        let mut subprojects = vec![];
+        let mut harvest_projects = vec![];
+        let mut contact_ids = targets.contacts;
+
+        for id in project_ids {
+            let Some(project) = self.rentman.get_project(id).await? else {
+                println!("Rentman project {} no longer exists, skipping", id);
+                continue;
+            };
+
+            if project.customer_id != 0 {
+                contact_ids.insert(project.customer_id);
+            }
+
+            if let Some(harvest_id) = mappings.projects.get(&id) {
+                harvest_projects.extend(self.harvest.get_project(*harvest_id).await?);
+            }
+
+            subprojects.extend(self.rentman.get_project_subprojects(id).await?);
+            rentman_projects.push(project);
+        }
+
+        let mut contacts = vec![];
+
+        for id in contact_ids {
+            match self.rentman.get_contact(id).await? {
+                Some(contact) => contacts.push(contact),
+                None => println!("Rentman contact {} no longer exists, skipping", id),
+            }
+        }
+
+        let mut input = Input {
+            complete: false,
+            clients: self.mapped_clients(mappings, &contacts).await?,
+            harvest_projects,
+            contacts,
+            rentman_projects,
+            subprojects,
+            statuses: self.rentman.get_statuses().await?,
+            functions: vec![],
+            tasks: vec![],
+            task_assignments: vec![],
+            users: vec![],
+            crew: vec![],
+            project_crew: vec![],
+            user_assignments: vec![],
+        };
+
+        if self.budget_by_hours() {
+            for project in &input.rentman_projects {
+                input
+                    .functions
+                    .extend(self.rentman.get_project_functions(Some(project.id)).await?);
+            }
+        }
+
+        if self.settings.sync_tasks {
+            input.tasks = self.harvest.get_tasks().await?;
+
+            for project in &input.harvest_projects {
+                input
+                    .task_assignments
+                    .extend(self.harvest.get_task_assignments(Some(project.id)).await?);
+            }
+        }
+
+        if self.settings.sync_users {
+            input.users = self.harvest.get_users().await?;
+            input.crew = self.rentman.get_crew().await?.data;
+
+            for project in &input.rentman_projects {
+                input
+                    .project_crew
+                    .extend(self.rentman.get_project_crew(Some(project.id)).await?);
+            }
+
+            for project in &input.harvest_projects {
+                input
+                    .user_assignments
+                    .extend(self.harvest.get_user_assignments(Some(project.id)).await?);
+            }
+        }
+
+        self.apply(mappings, input, shutdown).await
+    }
+
+    fn budget_by_hours(&self) -> bool {
+        self.settings
+            .budget
+            .as_ref()
+            .is_some_and(|x| x.source == BudgetSource::Hours)
+    }
+
+    /// Fetches the Harvest clients mapped to `contacts`.
+    async fn mapped_clients(
+        &self,
+        mappings: &MappingStore,
+        contacts: &[ContactsData],
+    ) -> Result<Vec<ClientData>, SyncError> {
+        let mut clients = vec![];
+
+        for contact in contacts {
+            if let Some(id) = mappings.clients.get(&contact.id) {
+                clients.extend(self.harvest.get_client(*id).await?);
+            }
+        }
+
+        Ok(clients)
+    }
+
+    async fn apply(
+        &self,
+        mappings: &mut MappingStore,
+        input: Input,
+        shutdown: &Shutdown,
+    ) -> Result<Failures, SyncError> {
+        let plan = plan::plan(&input, mappings, &self.settings, Utc::now());
+
+        Ok(executor::apply(&self.harvest, mappings, plan, self.dry_run, shutdown).await)
+    }
+}
@@ -0,0 +1,587 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeDelta, Utc};
+
+use crate::crew;
+use crate::error::SyncError;
+use crate::harvest::{self, HarvestClient};
+use crate::mapping::{MappingStore, SyncedTimeEntry};
+use crate::rentman::{RentmanClient, TimeRegistration};
+use crate::schedule::Shutdown;
+
+/// Harvest only records hours per day, so registrations start at this local time.
+const DAY_START: NaiveTime = match NaiveTime::from_hms_opt(9, 0, 0) {
+    Some(x) => x,
+    None => panic!("Invalid day start"),
+};
+
+/// Which Harvest time entries are written to Rentman time registration.
+#[derive(Debug, Clone)]
+pub struct TimeEntrySettings {
+    /// Entries spent up to this many days ago are synced, so late edits are picked up.
+    pub days: i64,
+}
+
+/// Time registrations to write to Rentman.
+#[derive(Debug, Default)]
+pub struct TimePlan {
+    pub actions: Vec<TimeAction>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum TimeAction {
+    Create {
+        entry_id: i64,
+        updated_at: String,
+        spent_date: NaiveDate,
+        registration: TimeRegistration,
+    },
+    Update {
+        entry_id: i64,
+        registration_id: i64,
+        updated_at: String,
+        spent_date: NaiveDate,
+        registration: TimeRegistration,
+    },
+    /// The time entry was deleted, or no longer belongs to a mapped project or crew member.
+    Delete { entry_id: i64, registration_id: i64 },
+}
+
+/// Computes the time registrations needed for the Harvest time entries spent from `from` to `to`
+/// on mapped projects. Entries that didn't change since they were written are skipped, and the
+/// registrations of entries in these days that are gone are deleted. Does not touch the network.
+pub fn plan(
+    entries: &[harvest::TimeEntry],
+    crew_by_user: &HashMap<i64, i64>,
+    mappings: &MappingStore,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> TimePlan {
+    let mut plan = TimePlan::default();
+
+    let rentman_projects = mappings.rentman_projects_by_project();
+
+    let mut unmapped_users = BTreeSet::new();
+    let mut kept = HashSet::new();
+
+    for entry in entries {
+        let Some(project_id) = rentman_projects.get(&entry.project.id) else {
+            continue;
+        };
+
+        let Some(crew_id) = crew_by_user.get(&entry.user.id) else {
+            unmapped_users.insert(entry.user.name.clone());
+            continue;
+        };
+
+        kept.insert(entry.id);
+
+        // Hours of a running timer are not final yet
+        if entry.is_running {
+            continue;
+        }
+
+        let synced = mappings.time_entries.get(&entry.id);
+
+        if synced.is_some_and(|x| x.updated_at == entry.updated_at) {
+            continue;
+        }
+
+        let registration = registration(entry, *project_id, *crew_id);
+
+        plan.actions.push(match synced.map(|x| x.registration_id) {
+            Some(Some(registration_id)) => TimeAction::Update {
+                entry_id: entry.id,
+                registration_id,
+                updated_at: entry.updated_at.clone(),
+                spent_date: entry.spent_date,
+                registration,
+            },
+            Some(None) => {
+                plan.warnings.push(pending_warning(entry.id));
+                continue;
+            }
+            None => TimeAction::Create {
+                entry_id: entry.id,
+                updated_at: entry.updated_at.clone(),
+                spent_date: entry.spent_date,
+                registration,
+            },
+        });
+    }
+
+    for (entry_id, synced) in &mappings.time_entries {
+        // Entries spent on other days weren't fetched, so they may still exist
+        if kept.contains(entry_id) || !synced.spent_date.is_some_and(|x| (from..=to).contains(&x)) {
+            continue;
+        }
+
+        match synced.registration_id {
+            Some(registration_id) => plan.actions.push(TimeAction::Delete {
+                entry_id: *entry_id,
+                registration_id,
+            }),
+            None => plan.warnings.push(pending_warning(*entry_id)),
+        }
+    }
+
+    for name in unmapped_users {
+        plan.warnings.push(format!(
+            "Harvest user {} has no Rentman crew member, add their ID to crew",
+            name
+        ));
+    }
+
+    plan
+}
+
+fn pending_warning(entry_id: i64) -> String {
+    format!(
+        "A sync stopped while registering Harvest time entry {0} in Rentman. Check Rentman for a registration with \"Harvest time entry {0}\" in its remark, and remove the entry from time_entries in the mapping file to write it again",
+        entry_id
+    )
+}
+
+fn registration(entry: &harvest::TimeEntry, project_id: i64, crew_id: i64) -> TimeRegistration {
+    let start: DateTime<Utc> = entry
+        .spent_date
+        .and_time(DAY_START)
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|x| x.with_timezone(&Utc))
+        .unwrap_or_else(|| entry.spent_date.and_time(DAY_START).and_utc());
+
+    let duration = TimeDelta::seconds((entry.hours * 3600.0).round() as i64);
+
+    let remark = match entry.notes.as_deref().map(str::trim) {
+        Some(notes) if !notes.is_empty() => format!("{}: {}", entry.task.name, notes),
+        _ => entry.task.name.clone(),
+    };
+
+    TimeRegistration {
+        crewmember: format!("/crew/{}", crew_id),
+        project: format!("/projects/{}", project_id),
+        start,
+        end: start + duration,
+        remark: format!("{} (Harvest time entry {})", remark, entry.id),
+    }
+}
+
+/// Writes the Harvest time entries of the last `settings.days` days to Rentman, or only prints
+/// the changes when `dry_run` is set. Users are matched to crew members with `crew::crew_by_user`.
+/// Returns the number of registrations that failed to write.
+pub async fn sync(
+    harvest: &HarvestClient,
+    rentman: &RentmanClient,
+    mappings: &mut MappingStore,
+    settings: &TimeEntrySettings,
+    crew_mapping: &HashMap<i64, i64>,
+    dry_run: bool,
+    shutdown: &Shutdown,
+) -> Result<usize, SyncError> {
+    let to = Local::now().date_naive();
+    let from = to - TimeDelta::days(settings.days);
+
+    let entries = harvest.get_time_entries(from, to, None).await?;
+    let users = harvest.get_users().await?;
+    let crew = rentman.get_crew().await?;
+
+    let crew_by_user = crew::crew_by_user(&users, &crew.data, crew_mapping);
+
+    let plan = plan(&entries, &crew_by_user, mappings, from, to);
+
+    for warning in &plan.warnings {
+        println!("{}", warning);
+    }
+
+    let mut failed = 0;
+
+    for action in plan.actions {
+        if shutdown.is_requested() {
+            break;
+        }
+
+        if dry_run {
+            println!("[dry-run] {}", action);
+            continue;
+        }
+
+        println!("{}", action);
+
+        if let Err(e) = apply_action(rentman, mappings, action).await {
+            eprintln!("Failed: {}", e);
+            failed += 1;
+        }
+    }
+
+    Ok(failed)
+}
+
+async fn apply_action(
+    rentman: &RentmanClient,
+    mappings: &mut MappingStore,
+    action: TimeAction,
+) -> Result<(), SyncError> {
+    // The mapping file is saved before and after every write, so a crash or a failed save can't
+    // book the same hours twice
+    match action {
+        TimeAction::Create {
+            entry_id,
+            updated_at,
+            spent_date,
+            registration,
+        } => {
+            mappings.time_entries.insert(
+                entry_id,
+                SyncedTimeEntry {
+                    registration_id: None,
+                    updated_at: String::new(),
+                    spent_date: Some(spent_date),
+                },
+            );
+
+            if let Err(e) = mappings.save() {
+                mappings.time_entries.remove(&entry_id);
+                return Err(e);
+            }
+
+            let created = match rentman.create_time_registration(&registration).await {
+                Ok(x) => x,
+                Err(e) if is_rejected(&e) => {
+                    mappings.time_entries.remove(&entry_id);
+                    mappings.save()?;
+                    return Err(e);
+                }
+                // Rentman may have saved it before the connection dropped or the response broke,
+                // so the marker stays and the next sync warns about it instead of registering again
+                Err(e) => return Err(e),
+            };
+
+            mappings.time_entries.insert(
+                entry_id,
+                SyncedTimeEntry {
+                    registration_id: Some(created.id),
+                    updated_at,
+                    spent_date: Some(spent_date),
+                },
+            );
+        }
+        TimeAction::Update {
+            entry_id,
+            registration_id,
+            updated_at,
+            spent_date,
+            registration,
+        } => {
+            rentman
+                .update_time_registration(registration_id, &registration)
+                .await?;
+
+            mappings.time_entries.insert(
+                entry_id,
+                SyncedTimeEntry {
+                    registration_id: Some(registration_id),
+                    updated_at,
+                    spent_date: Some(spent_date),
+                },
+            );
+        }
+        TimeAction::Delete {
+            entry_id,
+            registration_id,
+        } => {
+            rentman.delete_time_registration(registration_id).await?;
+
+            mappings.time_entries.remove(&entry_id);
+        }
+    }
+
+    mappings.save()
+}
+
+/// Whether Rentman refused a request, so it certainly didn't save anything.
+fn is_rejected(e: &SyncError) -> bool {
+    matches!(e, SyncError::Api { status, .. } if status.is_client_error())
+}
+
+impl fmt::Display for TimeAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (verb, registration) = match self {
+            TimeAction::Create { registration, .. } => ("Register", registration),
+            TimeAction::Update { registration, .. } => ("Update registration of", registration),
+            TimeAction::Delete {
+                entry_id,
+                registration_id,
+            } => {
+                return write!(
+                    f,
+                    "Delete registration {} of Harvest time entry {}",
+                    registration_id, entry_id
+                )
+            }
+        };
+
+        write!(
+            f,
+            "{} {} hours for {} on {} ({})",
+            verb,
+            (registration.end - registration.start).num_minutes() as f64 / 60.0,
+            registration.crewmember,
+            registration.project,
+            registration.start.with_timezone(&Local).date_naive()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn day(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    fn entry(id: i64, spent_day: u32, updated_at: &str) -> harvest::TimeEntry {
+        serde_json::from_value(json!({
+            "id": id,
+            "spent_date": day(spent_day),
+            "hours": 2.5,
+            "notes": "Kabels",
+            "is_running": false,
+            "updated_at": updated_at,
+            "user": { "id": 20, "name": "Sam" },
+            "project": { "id": 10, "name": "Festival" },
+            "task": { "id": 30, "name": "Opbouw" },
+        }))
+        .unwrap()
+    }
+
+    fn synced(registration_id: Option<i64>, spent_day: u32, updated_at: &str) -> SyncedTimeEntry {
+        SyncedTimeEntry {
+            registration_id,
+            updated_at: updated_at.to_string(),
+            spent_date: Some(day(spent_day)),
+        }
+    }
+
+    fn mappings(time_entries: BTreeMap<i64, SyncedTimeEntry>) -> MappingStore {
+        let mut mappings = MappingStore::default();
+        mappings.projects.insert(1001, 10);
+        mappings.time_entries = time_entries;
+        mappings
+    }
+
+    fn crew() -> HashMap<i64, i64> {
+        HashMap::from([(20, 40)])
+    }
+
+    #[test]
+    fn plan_creates_new_entries() {
+        let plan = plan(
+            &[entry(1, 2, "a")],
+            &crew(),
+            &mappings(BTreeMap::new()),
+            day(1),
+            day(7),
+        );
+
+        assert!(plan.warnings.is_empty());
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [TimeAction::Create { entry_id: 1, registration, .. }]
+                if registration.project == "/projects/1001"
+                    && registration.crewmember == "/crew/40"
+                    && registration.end - registration.start == TimeDelta::minutes(150)
+                    && registration.remark == "Opbouw: Kabels (Harvest time entry 1)"
+        ));
+    }
+
+    #[test]
+    fn plan_skips_unchanged_and_updates_changed_entries() {
+        let mappings = mappings(BTreeMap::from([
+            (1, synced(Some(100), 2, "a")),
+            (2, synced(Some(200), 2, "a")),
+        ]));
+
+        let plan = plan(
+            &[entry(1, 2, "a"), entry(2, 2, "b")],
+            &crew(),
+            &mappings,
+            day(1),
+            day(7),
+        );
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [TimeAction::Update {
+                entry_id: 2,
+                registration_id: 200,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn plan_deletes_registrations_of_gone_entries_in_window() {
+        let mappings = mappings(BTreeMap::from([
+            (1, synced(Some(100), 2, "a")),
+            (2, synced(Some(200), 20, "a")),
+        ]));
+
+        let plan = plan(&[], &crew(), &mappings, day(1), day(7));
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [TimeAction::Delete {
+                entry_id: 1,
+                registration_id: 100,
+            }]
+        ));
+    }
+
+    #[test]
+    fn plan_warns_about_pending_registrations() {
+        let mappings = mappings(BTreeMap::from([
+            (1, synced(None, 2, "")),
+            (2, synced(None, 3, "")),
+        ]));
+
+        let plan = plan(&[entry(1, 2, "a")], &crew(), &mappings, day(1), day(7));
+
+        assert!(plan.actions.is_empty());
+        assert_eq!(plan.warnings, vec![pending_warning(1), pending_warning(2)]);
+    }
+
+    #[test]
+    fn plan_deletes_entries_of_unmapped_users() {
+        let mappings = mappings(BTreeMap::from([(1, synced(Some(100), 2, "a"))]));
+
+        let plan = plan(
+            &[entry(1, 2, "a")],
+            &HashMap::new(),
+            &mappings,
+            day(1),
+            day(7),
+        );
+
+        assert!(matches!(
+            plan.actions.as_slice(),
+            [TimeAction::Delete { entry_id: 1, .. }]
+        ));
+        assert_eq!(plan.warnings.len(), 1);
+    }
+
+    /// A Rentman API that answers every request with `status`, or drops the connection when it
+    /// is `None`.
+    async fn rentman(status: Option<&'static str>) -> RentmanClient {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = vec![];
+                let mut buf = [0; 4096];
+
+                // Read the headers and body, so the client doesn't see a reset
+                while let Ok(n @ 1..) = socket.read(&mut buf).await {
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|x| {
+                                x.to_lowercase()
+                                    .strip_prefix("content-length:")
+                                    .map(|x| x.trim().parse::<usize>().unwrap())
+                            })
+                            .unwrap_or(0);
+
+                        if body.len() >= length {
+                            break;
+                        }
+                    }
+                }
+
+                if let Some(status) = status {
+                    let response = format!(
+                        "HTTP/1.1 {}\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{{}}",
+                        status
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                }
+            }
+        });
+
+        RentmanClient::new("token").unwrap().with_base_url(&url)
+    }
+
+    /// Registers entry 1 against `rentman`, returning what the mapping file holds afterwards.
+    async fn create(rentman: RentmanClient, name: &str) -> (Result<(), SyncError>, MappingStore) {
+        let path = std::env::temp_dir().join(format!(
+            "rentman-harvest-{}-{}.json",
+            std::process::id(),
+            name
+        ));
+
+        let mut mappings = MappingStore::load(&path, true).unwrap();
+        let entry = entry(1, 2, "a");
+
+        let action = TimeAction::Create {
+            entry_id: 1,
+            updated_at: "a".to_string(),
+            spent_date: entry.spent_date,
+            registration: registration(&entry, 1001, 40),
+        };
+
+        let result = apply_action(&rentman, &mut mappings, action).await;
+        let saved = MappingStore::load(&path, false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        (result, saved)
+    }
+
+    #[tokio::test]
+    async fn create_keeps_pending_marker_when_rentman_may_have_saved_it() {
+        for (status, name) in [
+            (Some("500 Internal Server Error"), "server-error"),
+            (Some("200 OK"), "bad-response"),
+            (None, "dropped"),
+        ] {
+            let (result, mut saved) = create(rentman(status).await, name).await;
+
+            assert!(result.is_err(), "{}", name);
+            assert!(
+                saved
+                    .time_entries
+                    .get(&1)
+                    .is_some_and(|x| x.registration_id.is_none()),
+                "{}",
+                name
+            );
+
+            // So the next sync warns instead of registering the hours again
+            saved.projects.insert(1001, 10);
+            let plan = plan(&[entry(1, 2, "a")], &crew(), &saved, day(1), day(7));
+            assert!(plan.actions.is_empty(), "{}", name);
+            assert_eq!(plan.warnings, vec![pending_warning(1)], "{}", name);
+        }
+    }
+
+    #[tokio::test]
+    async fn create_forgets_rejected_registration() {
+        let (result, saved) =
+            create(rentman(Some("422 Unprocessable Entity")).await, "rejected").await;
+
+        assert!(matches!(result, Err(SyncError::Api { .. })));
+        assert!(saved.time_entries.is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::error::SyncError;
+use crate::schedule::Shutdown;
+use crate::sync::{Syncer, Targets};
+
+/// Number of webhooks that can wait for a sync before new ones are rejected.
+const QUEUE_SIZE: usize = 1000;
+
+/// Where the webhook endpoint listens, and the token Rentman must send.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub listen: SocketAddr,
+    pub token: String,
+}
+
+/// A Rentman webhook body. Only the fields needed to find the changed records are read.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Payload {
+    pub event_type: String,
+    pub item_type: String,
+    pub items: Vec<Item>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Item {
+    pub id: i64,
+    pub parent: Option<Parent>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Parent {
+    #[serde(rename = "ref")]
+    pub reference: Option<String>,
+}
+
+impl Payload {
+    /// Records to sync for this event. Deleted projects and contacts are left to the full sync.
+    pub fn targets(&self) -> Targets {
+        let deleted = self.event_type.eq_ignore_ascii_case("delete");
+        let mut targets = Targets::default();
+
+        for item in &self.items {
+            match self.item_type.to_lowercase().as_str() {
+                "project" if !deleted => {
+                    targets.projects.insert(item.id);
+                }
+                "contact" if !deleted => {
+                    targets.contacts.insert(item.id);
+                }
+                // Resolve the project from the parent, which is still there when a subproject is deleted
+                "subproject" => match item.parent_project() {
+                    Some(project_id) => {
+                        targets.projects.insert(project_id);
+                    }
+                    None if !deleted => {
+                        targets.subprojects.insert(item.id);
+                    }
+                    None => {}
+                },
+                _ => {}
+            }
+        }
+
+        targets
+    }
+}
+
+impl Item {
+    fn parent_project(&self) -> Option<i64> {
+        self.parent
+            .as_ref()?
+            .reference
+            .as_deref()?
+            .strip_prefix("/projects/")?
+            .parse()
+            .ok()
+    }
+}
+
+#[derive(Clone)]
+struct AppState {
+    token: Arc<str>,
+    queue: mpsc::Sender<Targets>,
+}
+
+/// The webhook endpoint, bound while setting up so a taken port stops the process right away.
+pub struct Server {
+    listener: std::net::TcpListener,
+    config: WebhookConfig,
+}
+
+impl Server {
+    pub fn bind(config: WebhookConfig) -> Result<Self, SyncError> {
+        let listener = std::net::TcpListener::bind(config.listen)
+            .and_then(|x| x.set_nonblocking(true).map(|_| x))
+            .map_err(|e| SyncError::Config(format!("Can't listen on {}: {}", config.listen, e)))?;
+
+        Ok(Self { listener, config })
+    }
+}
+
+/// Serves `POST /rentman/webhook?token=...` until a shutdown is requested. Accepted webhooks
+/// are queued and synced one at a time, in between scheduled syncs.
+pub async fn serve(
+    server: Server,
+    syncer: Arc<Syncer>,
+    mut shutdown: Shutdown,
+) -> Result<(), SyncError> {
+    let Server { listener, config } = server;
+
+    let listener = tokio::net::TcpListener::from_std(listener)
+        .map_err(|e| SyncError::Config(format!("Can't listen on {}: {}", config.listen, e)))?;
+
+    let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+
+    let worker = tokio::spawn(work(rx, syncer, shutdown.clone()));
+
+    let app = Router::new()
+        .route("/rentman/webhook", post(receive))
+        .with_state(AppState {
+            token: config.token.into(),
+            queue: tx,
+        });
+
+    println!("Listening for Rentman webhooks on {}", config.listen);
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.requested().await })
+        .await
+        .map_err(|e| SyncError::Config(format!("Webhook server failed: {}", e)))?;
+
+    // The router owned the last sender, so the worker stops once the queue is drained
+    let _ = worker.await;
+
+    Ok(())
+}
+
+async fn receive(
+    State(state): State<AppState>,
+    Query(query): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> (StatusCode, String) {
+    let token = query.get("token").map(String::as_str).unwrap_or_default();
+
+    if !constant_time_eq(token.as_bytes(), state.token.as_bytes()) {
+        return (StatusCode::UNAUTHORIZED, "Invalid token".to_string());
+    }
+
+    let payload = match serde_json::from_slice::<Payload>(&body) {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("Invalid webhook: {}", e);
+            return (StatusCode::BAD_REQUEST, format!("Invalid payload: {}", e));
+        }
+    };
+
+    let targets = payload.targets();
+
+    println!(
+        "Webhook: {} {} {:?}",
+        payload.event_type,
+        payload.item_type,
+        payload.items.iter().map(|x| x.id).collect::<Vec<i64>>()
+    );
+
+    if targets.is_empty() {
+        return (StatusCode::ACCEPTED, "Nothing to sync".to_string());
+    }
+
+    if let Err(e) = state.queue.try_send(targets) {
+        eprintln!("Webhook queue is full, dropping {:?}", e.into_inner());
+        return (StatusCode::SERVICE_UNAVAILABLE, "Queue is full".to_string());
+    }
+
+    (StatusCode::ACCEPTED, "Accepted".to_string())
+}
+
+/// Syncs queued targets until the queue closes or a shutdown is requested.
+/// Webhooks that arrive while a sync runs are combined into the next one.
+async fn work(mut rx: mpsc::Receiver<Targets>, syncer: Arc<Syncer>, shutdown: Shutdown) {
+    while let Some(mut targets) = rx.recv().await {
+        while let Ok(more) = rx.try_recv() {
+            targets.extend(more);
+        }
+
+        if shutdown.is_requested() {
+            return;
+        }
+
+        match syncer.targets(targets, &shutdown).await {
+            Ok(0) => {}
+            Ok(failed) => eprintln!("Webhook sync finished with {} failed changes", failed),
+            Err(e) => eprintln!("Webhook sync failed: {}", e),
+        }
+    }
+}
+
+/// Compares without returning early, so the token can't be guessed from response times.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Projects, subprojects and contacts to sync for a webhook body.
+    fn targets(body: serde_json::Value) -> [Vec<i64>; 3] {
+        let targets = serde_json::from_value::<Payload>(body).unwrap().targets();
+
+        [
+            targets.projects.into_iter().collect(),
+            targets.subprojects.into_iter().collect(),
+            targets.contacts.into_iter().collect(),
+        ]
+    }
+
+    #[test]
+    fn targets_changed_projects_and_contacts() {
+        let project = json!({
+            "eventType": "update",
+            "itemType": "Project",
+            "items": [{ "id": 5 }, { "id": 6, "parent": null }],
+        });
+        let contact = json!({
+            "eventType": "create",
+            "itemType": "Contact",
+            "items": [{ "id": 7 }],
+        });
+
+        assert_eq!(targets(project), [vec![5, 6], vec![], vec![]]);
+        assert_eq!(targets(contact), [vec![], vec![], vec![7]]);
+    }
+
+    #[test]
+    fn targets_project_of_subproject() {
+        let body = json!({
+            "eventType": "update",
+            "itemType": "Subproject",
+            "items": [
+                { "id": 501, "parent": { "ref": "/projects/5" } },
+                { "id": 601, "parent": { "ref": null } },
+                { "id": 701 },
+            ],
+        });
+
+        assert_eq!(targets(body), [vec![5], vec![601, 701], vec![]]);
+    }
+
+    #[test]
+    fn leaves_deleted_records_to_full_sync() {
+        let project = json!({
+            "eventType": "delete",
+            "itemType": "Project",
+            "items": [{ "id": 5 }],
+        });
+        let contact = json!({
+            "eventType": "Delete",
+            "itemType": "Contact",
+            "items": [{ "id": 7 }],
+        });
+        let subproject = json!({
+            "eventType": "delete",
+            "itemType": "Subproject",
+            "items": [
+                { "id": 501, "parent": { "ref": "/projects/5" } },
+                { "id": 601 },
+            ],
+        });
+
+        assert!(targets(project).iter().all(Vec::is_empty));
+        assert!(targets(contact).iter().all(Vec::is_empty));
+        assert_eq!(targets(subproject), [vec![5], vec![], vec![]]);
+    }
+
+    #[test]
+    fn ignores_other_item_types() {
+        let body = json!({
+            "eventType": "update",
+            "itemType": "Equipment",
+            "items": [{ "id": 5 }],
+        });
+
+        assert!(targets(body).iter().all(Vec::is_empty));
+    }
+
+    #[test]
+    fn constant_time_eq_rejects_wrong_token() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"", b"secret"));
+    }
+}